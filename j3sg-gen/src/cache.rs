@@ -0,0 +1,328 @@
+//! A persisted cache for incremental builds
+//!
+//! The cache lives in a single `manifest.json` inside the cache directory,
+//! `.j3sg-cache` by default. It records
+//!
+//! * For every source file, a hash of its contents and the markdown options
+//!   along with the result of parsing it, so that unchanged files are not
+//!   parsed again
+//! * For every output file, a fingerprint of everything it was rendered from,
+//!   so that pages whose inputs have not changed are not rendered again
+//! * Every file written to the output directory, so that files a build no
+//!   longer writes, e.g of deleted pages or drafts, can be removed
+//!
+//! Only entries used by the current build are saved, so deleted pages drop
+//! out of the cache. Deleting the cache directory forces a full rebuild.
+
+use crate::error::{Error, Result};
+use crate::parse::{MarkdownOptions, Parse};
+use crate::links::Links;
+
+use std::fs;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// The name of the cache manifest within the cache directory
+const MANIFEST: &str = "manifest.json";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    /// Parsed source files, by path
+    sources: HashMap<PathBuf, CachedParse>,
+    /// Fingerprints of the inputs each output file was rendered from, by path
+    outputs: HashMap<PathBuf, String>,
+    /// Every file the build wrote, or would have written had it changed
+    #[serde(default)]
+    files: HashSet<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedParse {
+    hash: String,
+    parse: Parse,
+}
+
+#[derive(Debug)]
+pub struct Cache {
+    dir: PathBuf,
+    /// The site's markdown options, which source files are parsed with
+    markdown: MarkdownOptions,
+    /// The source files links may point to
+    links: Links,
+    /// The manifest saved by the previous build
+    previous: Manifest,
+    /// The manifest of the current build
+    current: Mutex<Manifest>,
+}
+
+impl Cache {
+    /// Loads the cache from `dir`
+    ///
+    /// A missing or unreadable cache is treated as empty.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The cache directory
+    /// * `markdown` - The site's markdown options
+    /// * `links` - The source files links may point to
+    pub fn load<P>(dir: P, markdown: MarkdownOptions, links: Links) -> Self
+    where
+        P: AsRef<Path>
+    {
+        let dir = dir.as_ref();
+        let path = dir.join(MANIFEST);
+        let previous = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .unwrap_or_else(|e| {
+                    warn!("Ignoring unreadable build cache {}: {}", path.display(), e);
+                    Manifest::default()
+                }),
+            Err(_) => Manifest::default(),
+        };
+
+        Self {
+            dir: dir.to_owned(),
+            markdown,
+            links,
+            previous,
+            current: Mutex::new(Manifest::default()),
+        }
+    }
+
+    /// Parses the source file `src`, whose contents are `text`, reusing
+    /// the previous result if neither its contents nor the markdown options
+    /// have changed, and its links to other source files still point to
+    /// the same URIs
+    ///
+    /// # Arguments
+    ///
+    /// * `src` - The path of the source file
+    /// * `text` - The contents of the source file
+    pub fn parse(&self, src: &Path, text: &str) -> Result<Parse> {
+        let options = serde_json::to_string(&self.markdown)
+            .expect("markdown options are always serializable");
+        let hash = hash(format!("{}\n{}", options, text).as_bytes());
+        let cached = |manifest: &Manifest| manifest.sources.get(src)
+            .filter(|cached| cached.hash == hash)
+            .filter(|cached| cached.parse.links.iter().all(|(url, uri)| {
+                matches!(self.links.resolve(src, url, None), Ok(Some(ref resolved)) if resolved == uri)
+            }))
+            .map(|cached| cached.parse.clone());
+
+        let cached = cached(&self.current.lock().unwrap())
+            .or_else(|| cached(&self.previous));
+        let parse = match cached {
+            Some(parse) => parse,
+            None => Parse::from_str(text, src, &self.markdown, &self.links)?,
+        };
+
+        self.current.lock().unwrap()
+            .sources.insert(src.to_owned(), CachedParse { hash, parse: parse.clone() });
+        Ok(parse)
+    }
+
+    /// Whether `out_path` exists and was last rendered from inputs with the
+    /// given fingerprint. Either way, the fingerprint is recorded for the
+    /// next build.
+    ///
+    /// # Arguments
+    ///
+    /// * `out_path` - The path of the output file
+    /// * `fingerprint` - The fingerprint of the inputs it is rendered from
+    pub fn is_fresh(&self, out_path: &Path, fingerprint: &str) -> bool {
+        let fresh = out_path.is_file()
+            && self.previous.outputs.get(out_path).map(String::as_str) == Some(fingerprint);
+
+        let mut current = self.current.lock().unwrap();
+        current.outputs.insert(out_path.to_owned(), fingerprint.to_string());
+        current.files.insert(out_path.to_owned());
+        fresh
+    }
+
+    /// Records that the current build wrote the file at `path`, so that it
+    /// is not removed by `prune`
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file
+    pub fn record(&self, path: &Path) {
+        self.current.lock().unwrap()
+            .files.insert(path.to_owned());
+    }
+
+    /// Removes every file under `out_dir` which the previous build wrote but
+    /// the current build did not, along with any directories this leaves
+    /// empty, returning how many files were removed
+    ///
+    /// # Arguments
+    ///
+    /// * `out_dir` - The output directory
+    pub fn prune(&self, out_dir: &Path) -> Result<usize> {
+        let current = self.current.lock().unwrap();
+        let mut stale: Vec<&PathBuf> = self.previous.files.iter()
+            .filter(|path| path.starts_with(out_dir) && !current.files.contains(*path))
+            .collect();
+        stale.sort();
+
+        let mut removed = 0;
+        for path in stale {
+            match fs::remove_file(path) {
+                Ok(()) => removed += 1,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(Error::io(path, e)),
+            }
+            // Only empty directories can be removed, so this stops at the
+            // first one still holding something
+            let mut dir = path.parent();
+            while let Some(parent) = dir.filter(|dir| *dir != out_dir && dir.starts_with(out_dir)) {
+                if fs::remove_dir(parent).is_err() {
+                    break;
+                }
+                dir = parent.parent();
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Forgets the fingerprint of `out_path`, e.g because rendering it failed
+    ///
+    /// # Arguments
+    ///
+    /// * `out_path` - The path of the output file
+    pub fn invalidate(&self, out_path: &Path) {
+        self.current.lock().unwrap()
+            .outputs.remove(out_path);
+    }
+
+    /// Saves the entries used by the current build
+    pub fn save(&self) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| Error::io(&self.dir, e))?;
+
+        let path = self.dir.join(MANIFEST);
+        let bytes = serde_json::to_vec(&*self.current.lock().unwrap())
+            .expect("cache manifest is always serializable");
+        fs::write(&path, bytes)
+            .map_err(|e| Error::io(&path, e))
+    }
+}
+
+/// Returns the hex encoded SHA-256 hash of `bytes`
+pub fn hash(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::sitemap::SiteMap;
+
+    fn cache_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("j3sg-test-cache-{}", name));
+        fs::remove_dir_all(&dir).unwrap_or(());
+        dir
+    }
+
+    #[test]
+    fn parses_survive_a_save() {
+        let dir = cache_dir("parse");
+        let src = Path::new("src/index.md");
+
+        let cache = Cache::load(&dir, MarkdownOptions::default(), Links::default());
+        let parse = cache.parse(src, "---\ntitle: Index\n---\n# Hi").unwrap();
+        cache.save().unwrap();
+
+        let cache = Cache::load(&dir, MarkdownOptions::default(), Links::default());
+        assert_eq!(cache.previous.sources[src].parse.title, parse.title);
+    }
+
+    #[test]
+    fn changed_options_parse_again() {
+        let dir = cache_dir("options");
+        let src = Path::new("src/index.md");
+
+        let cache = Cache::load(&dir, MarkdownOptions::default(), Links::default());
+        let parse = cache.parse(src, "<b>Hi</b>").unwrap();
+        assert!(parse.content.contains("<b>Hi</b>"));
+        cache.save().unwrap();
+
+        let cache = Cache::load(&dir, MarkdownOptions { html: false, ..Default::default() }, Links::default());
+        let parse = cache.parse(src, "<b>Hi</b>").unwrap();
+        assert!(parse.content.contains("&lt;b&gt;"));
+    }
+
+    #[test]
+    fn moved_link_targets_parse_again() {
+        let dir = cache_dir("links");
+        let src_dir = dir.join("src");
+        let site = |files: &[&str]| {
+            fs::remove_dir_all(&src_dir).unwrap_or(());
+            for file in files {
+                let path = src_dir.join(file);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, "").unwrap();
+            }
+            Links::new(&SiteMap::new(&src_dir).unwrap())
+        };
+        let src = src_dir.join("index.md");
+        let text = "[a](b/a.md)";
+
+        // Pages in a directory without an index.md belong to the parent
+        // section, until the directory gets an index.md of its own
+        let cache = Cache::load(&dir, MarkdownOptions::default(), site(&["index.md", "b/a.md"]));
+        assert!(cache.parse(&src, text).unwrap().content.contains("href=\"/a\""));
+        cache.save().unwrap();
+
+        let cache = Cache::load(&dir, MarkdownOptions::default(), site(&["index.md", "b/index.md", "b/a.md"]));
+        assert!(cache.parse(&src, text).unwrap().content.contains("href=\"/b/a\""));
+    }
+
+    #[test]
+    fn outputs_are_fresh_when_fingerprint_matches() {
+        let dir = cache_dir("outputs");
+        let out_path = dir.join("index.html");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&out_path, "").unwrap();
+
+        let cache = Cache::load(&dir, MarkdownOptions::default(), Links::default());
+        assert!(!cache.is_fresh(&out_path, "a"));
+        cache.save().unwrap();
+
+        let cache = Cache::load(&dir, MarkdownOptions::default(), Links::default());
+        assert!(cache.is_fresh(&out_path, "a"));
+        assert!(!cache.is_fresh(&out_path, "b"));
+
+        fs::remove_file(&out_path).unwrap();
+        assert!(!cache.is_fresh(&out_path, "a"));
+    }
+
+    #[test]
+    fn files_no_longer_written_are_pruned() {
+        let dir = cache_dir("prune");
+        let out_dir = dir.join("public");
+        let kept = out_dir.join("index.html");
+        let draft = out_dir.join("blog/draft/index.html");
+        for path in [&kept, &draft] {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+
+        let cache = Cache::load(&dir, MarkdownOptions::default(), Links::default());
+        cache.is_fresh(&kept, "a");
+        cache.record(&draft);
+        cache.save().unwrap();
+
+        let cache = Cache::load(&dir, MarkdownOptions::default(), Links::default());
+        cache.is_fresh(&kept, "a");
+        assert_eq!(cache.prune(&out_dir).unwrap(), 1);
+        assert!(kept.is_file());
+        assert!(!draft.exists());
+        assert!(!out_dir.join("blog").exists());
+        assert!(out_dir.is_dir());
+    }
+}
@@ -0,0 +1,281 @@
+//! Site configuration
+//!
+//! j3sg reads its configuration from a `j3sg.toml` file in the site's
+//! root directory. Every field is optional, e.g
+//!
+//! ```toml
+//! base_url = "https://example.com"
+//! title = "My Site"
+//! author = "Alice"
+//! default_template = "base.html"
+//!
+//! src_dir = "src"
+//! out_dir = "public"
+//!
+//! [extra]
+//! github = "https://github.com/alice"
+//! ```
+//!
+//! The whole configuration is exposed to templates as the global
+//! `config` object.
+
+use crate::render::DEFAULT_TEMPLATE;
+use crate::parse::MarkdownOptions;
+use crate::uri::Uri;
+use crate::highlight;
+use crate::schema::Schema;
+use crate::error::{Error, Result};
+
+use std::fs;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use toml::Value;
+
+/// The directory within `cache_dir` previews are built in
+pub const PREVIEW_DIR: &str = "preview";
+
+/// The name of the configuration file
+pub const CONFIG_FILE: &str = "j3sg.toml";
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The URL the site will be served from, e.g `https://example.com`
+    pub base_url: String,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub description: Option<String>,
+    /// The template used by pages which do not name one
+    pub default_template: String,
+
+    /// Directory containing the markdown source files
+    pub src_dir: PathBuf,
+    /// Directory the generated site is written to
+    pub out_dir: PathBuf,
+    /// Directory containing Tera templates
+    pub template_dir: PathBuf,
+    /// Directory containing static files
+    pub static_dir: PathBuf,
+    /// Where static files are copied to, relative to `out_dir`
+    pub static_mount: PathBuf,
+    /// Directory the incremental build cache is kept in
+    pub cache_dir: PathBuf,
+    /// Address the development server binds to
+    pub bind: String,
+    /// Whether drafts and pages dated in the future are built
+    pub drafts: bool,
+
+    /// The maximum number of items in a feed
+    pub feed_limit: usize,
+    /// Whether feed items contain each page's full content,
+    /// or just its description
+    pub feed_content: FeedContent,
+
+    /// The rules written to `robots.txt`, which is followed by a link
+    /// to `sitemap.xml`
+    pub robots: String,
+
+    /// Which markdown extensions are enabled
+    pub markdown: MarkdownOptions,
+
+    /// The names of the taxonomies collected from page front matter,
+    /// e.g `tags`
+    pub taxonomies: Vec<String>,
+
+    /// The front matter fields every page is expected to have
+    pub schema: Schema,
+
+    /// Arbitrary values for use in templates
+    pub extra: HashMap<String, Value>,
+}
+
+/// What feed items contain
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FeedContent {
+    /// Each page's description and full content
+    Full,
+    /// Only each page's description
+    Summary,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            base_url: String::new(),
+            title: None,
+            author: None,
+            description: None,
+            default_template: DEFAULT_TEMPLATE.to_string(),
+
+            src_dir: PathBuf::from("src"),
+            out_dir: PathBuf::from("public"),
+            template_dir: PathBuf::from("templates"),
+            static_dir: PathBuf::from("static"),
+            static_mount: PathBuf::from("static"),
+            cache_dir: PathBuf::from(".j3sg-cache"),
+            bind: "127.0.0.1:5000".to_string(),
+            drafts: false,
+
+            feed_limit: 20,
+            feed_content: FeedContent::Full,
+
+            robots: "User-agent: *\nAllow: /".to_string(),
+
+            markdown: MarkdownOptions::default(),
+
+            taxonomies: Vec::new(),
+
+            schema: Schema::default(),
+
+            extra: HashMap::new(),
+        }
+    }
+}
+
+impl FromStr for Config {
+    type Err = toml::de::Error;
+
+    /// Parses a configuration from the contents of a `j3sg.toml` file
+    fn from_str(text: &str) -> std::result::Result<Self, Self::Err> {
+        toml::from_str(text)
+    }
+}
+
+impl Config {
+    /// Loads the configuration file at `path`
+    ///
+    /// Relative directories are resolved against the directory containing
+    /// the configuration file. If there is no file at `path`, the default
+    /// configuration is used.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the configuration file
+    pub fn load<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>
+    {
+        let path = path.as_ref();
+        let mut config = if path.is_file() {
+            let text = fs::read_to_string(path)
+                .map_err(|e| Error::io(path, e))?;
+            Self::from_str(&text)
+                .map_err(|source| Error::Config { path: path.to_owned(), source })?
+        } else {
+            Self::default()
+        };
+
+        let invalid_name = |name: &&String| name.is_empty()
+            || name.contains('/')
+            || Uri::new().join(name).is_none();
+        if let Some(name) = config.taxonomies.iter().find(invalid_name) {
+            return Err(Error::Uri {
+                path: path.to_owned(),
+                message: format!("taxonomy name \"{}\" is not a valid URI segment", name),
+            });
+        }
+
+        if !highlight::theme_names().contains(&config.markdown.highlight_theme.as_str()) {
+            return Err(Error::Theme {
+                path: path.to_owned(),
+                theme: config.markdown.highlight_theme,
+            });
+        }
+
+        if let Some(root) = path.parent() {
+            for dir in [
+                &mut config.src_dir,
+                &mut config.out_dir,
+                &mut config.template_dir,
+                &mut config.static_dir,
+                &mut config.cache_dir,
+            ] {
+                *dir = root.join(&dir);
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Returns this configuration as used to preview the site, which builds
+    /// drafts into `cache_dir` rather than `out_dir`, so that they never
+    /// end up in the site which is deployed
+    pub fn preview(self) -> Self {
+        let preview = self.cache_dir.join(PREVIEW_DIR);
+        Self {
+            drafts: true,
+            out_dir: preview.join("public"),
+            cache_dir: preview,
+            ..self
+        }
+    }
+
+    /// Returns `uri`, which should begin with a `/`, as an absolute URL
+    /// on this site
+    ///
+    /// # Arguments
+    ///
+    /// * `uri` - The URI to make absolute
+    pub fn absolute_url(&self, uri: &str) -> String {
+        format!("{}{}", self.base_url.trim_end_matches('/'), uri)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn empty_config_is_default() {
+        let config = Config::from_str("").unwrap();
+        assert_eq!(config.default_template, DEFAULT_TEMPLATE);
+        assert_eq!(config.src_dir, PathBuf::from("src"));
+        assert_eq!(config.bind, "127.0.0.1:5000");
+    }
+
+    #[test]
+    fn previews_are_built_into_the_cache() {
+        let config = Config::default().preview();
+        assert!(config.drafts);
+        assert_eq!(config.out_dir, PathBuf::from(".j3sg-cache/preview/public"));
+        assert_eq!(config.cache_dir, PathBuf::from(".j3sg-cache/preview"));
+    }
+
+    #[test]
+    fn parse_config() {
+        let config = Config::from_str("
+            base_url = \"https://example.com/\"
+            title = \"My Site\"
+            out_dir = \"dist\"
+
+            [extra]
+            github = \"https://github.com/alice\"
+        ").unwrap();
+        assert_eq!(config.title.as_deref(), Some("My Site"));
+        assert_eq!(config.out_dir, PathBuf::from("dist"));
+        assert_eq!(config.template_dir, PathBuf::from("templates"));
+        assert!(config.extra.contains_key("github"));
+        assert_eq!(config.absolute_url("/blog"), "https://example.com/blog");
+    }
+
+    #[test]
+    fn invalid_taxonomy_name_is_an_error() {
+        let dir = std::env::temp_dir().join("j3sg-test-config-taxonomy");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(CONFIG_FILE);
+
+        fs::write(&path, "taxonomies = [\"tags\"]").unwrap();
+        assert_eq!(Config::load(&path).unwrap().taxonomies, ["tags"]);
+
+        fs::write(&path, "taxonomies = [\"my tags\"]").unwrap();
+        assert!(matches!(Config::load(&path), Err(Error::Uri { .. })));
+    }
+
+    #[test]
+    fn unknown_field_is_an_error() {
+        assert!(Config::from_str("titel = \"typo\"").is_err());
+    }
+}
@@ -0,0 +1,148 @@
+//! Files for search engine crawlers
+//!
+//! Every generated page is listed in `sitemap.xml`, following
+//! <https://www.sitemaps.org/protocol.html>, unless its front matter sets
+//! `sitemap: false`. A page's `lastmod` is its `updated` date, then its
+//! `date`, and then the modification time of its source file.
+//!
+//! `robots.txt` holds the `robots` rules from the configuration, followed by
+//! the location of `sitemap.xml`.
+//!
+//! Both must give absolute URLs, so `base_url` should be set in the
+//! configuration.
+
+use crate::config::Config;
+use crate::page::{Page, PageMap};
+use crate::section::SectionMap;
+use crate::date::Date;
+use crate::feed::escape;
+use crate::cache::Cache;
+use crate::filesystem;
+use crate::error::{Error, Result};
+
+use std::fs;
+use std::fmt::Write;
+use chrono::{DateTime, Utc};
+use log::warn;
+
+/// The file name of the sitemap
+pub const SITEMAP_FILE: &str = "sitemap.xml";
+/// The file name of the robots exclusion file
+pub const ROBOTS_FILE: &str = "robots.txt";
+
+/// Writes `sitemap.xml` and `robots.txt` to the root of the output directory,
+/// returning the number of pages in the sitemap
+///
+/// # Arguments
+///
+/// * `config` - The site configuration
+/// * `sectionmap` - The sections of the site
+/// * `pagemap` - The pages of the site
+/// * `cache` - The build cache, which every file written is recorded in
+pub fn write_crawler_files(
+    config: &Config,
+    sectionmap: &SectionMap,
+    pagemap: &PageMap,
+    cache: &Cache
+) -> Result<usize> {
+    if config.base_url.is_empty() {
+        warn!("base_url is not set, so links in {} and {} will not be absolute", SITEMAP_FILE, ROBOTS_FILE);
+    }
+
+    let indexes = sectionmap.0.values()
+        .filter_map(|section| section.index.as_ref());
+    let mut pages: Vec<&Page> = pagemap.0.values()
+        .chain(indexes)
+        .filter(|page| page.sitemap)
+        .collect();
+    pages.sort_by(|a, b| a.uri.cmp(&b.uri));
+
+    let entries = pages.into_iter()
+        .map(|page| Ok((page, lastmod(page)?)))
+        .collect::<Result<Vec<_>>>()?;
+
+    for (name, text) in [(SITEMAP_FILE, sitemap(config, &entries)), (ROBOTS_FILE, robots(config))] {
+        let path = config.out_dir.join(name);
+        filesystem::write(&path, text.as_bytes())?;
+        cache.record(&path);
+    }
+    Ok(entries.len())
+}
+
+/// Renders a sitemap listing each page along with when it was last modified
+///
+/// # Arguments
+///
+/// * `config` - The site configuration
+/// * `entries` - The pages of the sitemap and their modification dates
+pub fn sitemap(config: &Config, entries: &[(&Page, Date)]) -> String {
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+    for (page, lastmod) in entries {
+        xml.push_str("<url>\n");
+        writeln!(xml, "<loc>{}</loc>", escape(&config.absolute_url(&page.uri.to_string()))).unwrap();
+        writeln!(xml, "<lastmod>{}</lastmod>", lastmod.to_rfc3339()).unwrap();
+        xml.push_str("</url>\n");
+    }
+    xml.push_str("</urlset>\n");
+    xml
+}
+
+/// Renders `robots.txt`
+///
+/// # Arguments
+///
+/// * `config` - The site configuration
+pub fn robots(config: &Config) -> String {
+    let rules = config.robots.trim_end();
+    let sitemap = config.absolute_url(&format!("/{}", SITEMAP_FILE));
+    if rules.is_empty() {
+        format!("Sitemap: {}\n", sitemap)
+    } else {
+        format!("{}\n\nSitemap: {}\n", rules, sitemap)
+    }
+}
+
+/// Returns when `page` was last modified
+fn lastmod(page: &Page) -> Result<Date> {
+    if let Some(date) = page.updated.or(page.date) {
+        return Ok(date);
+    }
+
+    let modified = fs::metadata(&page.src)
+        .and_then(|metadata| metadata.modified())
+        .map_err(|e| Error::io(&page.src, e))?;
+    Ok(DateTime::<Utc>::from(modified).fixed_offset())
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::uri::Uri;
+    use crate::date;
+
+    #[test]
+    fn sitemap_entries() {
+        let config = Config { base_url: "https://example.com".to_string(), ..Config::default() };
+        let root = Page::test(Uri::new());
+        let post = Page::test(Uri::new().join("post").unwrap());
+        let date = date::parse("2023-09-01").unwrap();
+        let xml = sitemap(&config, &[(&root, date), (&post, date)]);
+
+        assert!(xml.contains("<loc>https://example.com/</loc>"));
+        assert!(xml.contains("<loc>https://example.com/post</loc>"));
+        assert!(xml.contains("<lastmod>2023-09-01T00:00:00+00:00</lastmod>"));
+        assert_eq!(xml.matches("<url>").count(), 2);
+    }
+
+    #[test]
+    fn robots_links_to_sitemap() {
+        let config = Config { base_url: "https://example.com/".to_string(), ..Config::default() };
+        assert_eq!(robots(&config),
+                   "User-agent: *\nAllow: /\n\nSitemap: https://example.com/sitemap.xml\n");
+
+        let config = Config { robots: String::new(), ..config };
+        assert_eq!(robots(&config), "Sitemap: https://example.com/sitemap.xml\n");
+    }
+}
@@ -0,0 +1,97 @@
+//! Parsing page dates
+//!
+//! A page's date may be given in its front matter, or as a prefix
+//! of its file name, e.g
+//!
+//! ```text
+//! src/blog/2023-09-01-hello-world.md
+//! ```
+//!
+//! Dates are serialized as RFC 3339 strings, which Tera's `date`
+//! filter understands.
+
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, Utc};
+
+/// A date with a timezone offset
+pub type Date = DateTime<FixedOffset>;
+
+/// Parses a date from a string in one of the following forms
+///
+/// * RFC 3339, e.g `2023-09-01T12:30:00-07:00`
+/// * A date and time without an offset, e.g `2023-09-01T12:30:00`
+///   or `2023-09-01 12:30:00`, which is taken to be UTC
+/// * A bare date, e.g `2023-09-01`, which is taken to be midnight UTC
+///
+/// # Arguments
+///
+/// * `s` - The string to parse
+pub fn parse(s: &str) -> Option<Date> {
+    let s = s.trim();
+    if let Ok(date) = DateTime::parse_from_rfc3339(s) {
+        return Some(date);
+    }
+
+    let naive = ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S"]
+        .iter()
+        .find_map(|fmt| NaiveDateTime::parse_from_str(s, fmt).ok())
+        .or_else(|| NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()
+            .and_then(|date| date.and_hms_opt(0, 0, 0)))?;
+
+    Some(DateTime::<Utc>::from_naive_utc_and_offset(naive, Utc).fixed_offset())
+}
+
+/// Parses the date prefix of a file stem of the form `YYYY-MM-DD-slug`
+///
+/// # Arguments
+///
+/// * `stem` - The file stem, i.e the file name without its extension
+pub fn from_file_stem(stem: &str) -> Option<Date> {
+    let prefix = stem.get(..10)?;
+    match stem.get(10..11) {
+        None | Some("-") | Some("_") => parse(prefix),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn parse_rfc3339() {
+        let date = parse("2023-09-01T12:30:00-07:00").unwrap();
+        assert_eq!(date.to_rfc3339(), "2023-09-01T12:30:00-07:00");
+    }
+
+    #[test]
+    fn parse_naive_datetime_as_utc() {
+        let date = parse("2023-09-01 12:30:00").unwrap();
+        assert_eq!(date.to_rfc3339(), "2023-09-01T12:30:00+00:00");
+    }
+
+    #[test]
+    fn parse_bare_date_as_midnight_utc() {
+        let date = parse("2023-09-01").unwrap();
+        assert_eq!(date.to_rfc3339(), "2023-09-01T00:00:00+00:00");
+    }
+
+    #[test]
+    fn parse_invalid_date() {
+        assert!(parse("September 1st").is_none());
+        assert!(parse("2023-13-01").is_none());
+    }
+
+    #[test]
+    fn date_from_file_stem() {
+        let date = from_file_stem("2023-09-01-hello-world").unwrap();
+        assert_eq!(date.to_rfc3339(), "2023-09-01T00:00:00+00:00");
+        assert!(from_file_stem("2023-09-01").is_some());
+    }
+
+    #[test]
+    fn no_date_in_file_stem() {
+        assert!(from_file_stem("hello-world").is_none());
+        assert!(from_file_stem("2023-09-012").is_none());
+        assert!(from_file_stem("2023").is_none());
+    }
+}
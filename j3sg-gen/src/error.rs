@@ -0,0 +1,313 @@
+//! The error type returned by j3sg-gen
+//!
+//! Every variant carries the path of the file that caused it, so
+//! that callers can point the user at the offending source.
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A specialized Result type for j3sg-gen
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing a file or directory failed
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// The configuration file could not be parsed
+    Config {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    /// A source file's front matter is invalid
+    FrontMatter {
+        path: PathBuf,
+        line: Option<usize>,
+        column: Option<usize>,
+        message: String,
+    },
+    /// A source file's markdown could not be parsed
+    Markdown {
+        path: PathBuf,
+        line: Option<usize>,
+        column: Option<usize>,
+        message: String,
+    },
+    /// Templates could not be loaded, or a page failed to render
+    ///
+    /// `path` is the page being rendered, or the template directory
+    /// if loading templates failed
+    Template {
+        path: PathBuf,
+        source: tera::Error,
+    },
+    /// A relative link in a source file points to a markdown file which
+    /// does not exist
+    BrokenLink {
+        path: PathBuf,
+        line: Option<usize>,
+        link: String,
+    },
+    /// A page uses a template that does not exist
+    MissingTemplate {
+        path: PathBuf,
+        template: String,
+    },
+    /// The configuration names a highlight theme which does not exist
+    Theme {
+        path: PathBuf,
+        theme: String,
+    },
+    /// A source file does not correspond to a valid URI
+    Uri {
+        path: PathBuf,
+        message: String,
+    },
+    /// A required source file does not exist
+    Missing {
+        path: PathBuf,
+    },
+    /// A directory could not be watched for changes
+    Watch {
+        path: PathBuf,
+        source: notify::Error,
+    },
+    /// Several source files would be rendered to the same URI
+    UriCollision {
+        uri: String,
+        paths: Vec<PathBuf>,
+    },
+    /// Several errors occurred, e.g in different source files
+    Multiple(Vec<Error>),
+}
+
+impl Error {
+    /// Returns an I/O error for `path`
+    pub fn io<P>(path: P, source: io::Error) -> Self
+    where
+        P: AsRef<Path>
+    {
+        Self::Io { path: path.as_ref().to_owned(), source }
+    }
+
+    /// Returns a markdown error for `path` from a message produced by the
+    /// markdown crate, which are of the form `line:column: reason`
+    pub fn markdown<P>(path: P, message: String) -> Self
+    where
+        P: AsRef<Path>
+    {
+        let mut parts = message.splitn(3, ':');
+        let location = (
+            parts.next().and_then(|s| s.parse().ok()),
+            parts.next().and_then(|s| s.parse().ok()),
+            parts.next(),
+        );
+        let (line, column, message) = match location {
+            (Some(line), Some(column), Some(reason)) => {
+                (Some(line), Some(column), reason.trim().to_string())
+            }
+            _ => (None, None, message),
+        };
+        Self::Markdown { path: path.as_ref().to_owned(), line, column, message }
+    }
+
+    /// Returns a single error if `errors` has exactly one element,
+    /// and `Multiple` otherwise
+    pub fn from_vec(mut errors: Vec<Error>) -> Self {
+        if errors.len() == 1 {
+            errors.remove(0)
+        } else {
+            Self::Multiple(errors)
+        }
+    }
+
+    /// Returns the path of the file which caused this error
+    ///
+    /// For errors involving several files, this is the first of them
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::Io { path, .. }
+            | Self::Config { path, .. }
+            | Self::FrontMatter { path, .. }
+            | Self::Markdown { path, .. }
+            | Self::Template { path, .. }
+            | Self::BrokenLink { path, .. }
+            | Self::MissingTemplate { path, .. }
+            | Self::Theme { path, .. }
+            | Self::Uri { path, .. }
+            | Self::Missing { path }
+            | Self::Watch { path, .. }
+            => Some(path),
+            Self::UriCollision { paths, .. } => paths.first().map(PathBuf::as_path),
+            Self::Multiple(errors) => errors.first().and_then(Error::path),
+        }
+    }
+}
+
+/// Collects `results` into a vector, or returns every error among them
+///
+/// Errors are ordered by path, so that they are reported in the same
+/// order regardless of the order `results` were produced in.
+pub fn collect<T, I>(results: I) -> Result<Vec<T>>
+where
+    I: IntoIterator<Item = Result<T>>
+{
+    let (oks, mut errors): (Vec<_>, Vec<_>) = results.into_iter()
+        .partition(|result| result.is_ok());
+    if errors.is_empty() {
+        return Ok(oks.into_iter().map(|result| result.ok().unwrap()).collect());
+    }
+
+    let mut errors: Vec<Error> = errors.drain(..)
+        .map(|result| result.err().unwrap())
+        .collect();
+    errors.sort_by(|a, b| a.path().cmp(&b.path()));
+    Err(Error::from_vec(errors))
+}
+
+/// Writes `path`, followed by `:line:column` if a line is known
+fn write_location(
+    f: &mut fmt::Formatter<'_>,
+    path: &Path,
+    line: &Option<usize>,
+    column: &Option<usize>
+) -> fmt::Result {
+    write!(f, "{}", path.display())?;
+    if let Some(line) = line {
+        write!(f, ":{}", line)?;
+        if let Some(column) = column {
+            write!(f, ":{}", column)?;
+        }
+    }
+    Ok(())
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, source } => {
+                write!(f, "{}: {}", path.display(), source)
+            }
+            Self::Config { path, source } => {
+                write!(f, "{}: invalid configuration: {}", path.display(), source)
+            }
+            Self::FrontMatter { path, line, column, message } => {
+                write_location(f, path, line, column)?;
+                write!(f, ": invalid front matter: {}", message)
+            }
+            Self::Markdown { path, line, column, message } => {
+                write_location(f, path, line, column)?;
+                write!(f, ": invalid markdown: {}", message)
+            }
+            Self::Template { path, source } => {
+                write!(f, "{}: {}", path.display(), source)?;
+                // Tera keeps the useful part of the message in the source chain
+                let mut cause = std::error::Error::source(source);
+                while let Some(e) = cause {
+                    write!(f, ": {}", e)?;
+                    cause = e.source();
+                }
+                Ok(())
+            }
+            Self::BrokenLink { path, line, link } => {
+                write_location(f, path, line, &None)?;
+                write!(f, ": broken link \"{}\", no such source file", link)
+            }
+            Self::MissingTemplate { path, template } => {
+                write!(f, "{}: template \"{}\" does not exist", path.display(), template)
+            }
+            Self::Theme { path, theme } => {
+                write!(f, "{}: highlight theme \"{}\" does not exist, expected one of {}",
+                       path.display(), theme, crate::highlight::theme_names().join(", "))
+            }
+            Self::Uri { path, message } => {
+                write!(f, "{}: {}", path.display(), message)
+            }
+            Self::Missing { path } => {
+                write!(f, "{}: required file not found", path.display())
+            }
+            Self::Watch { path, source } => {
+                write!(f, "{}: unable to watch for changes: {}", path.display(), source)
+            }
+            Self::UriCollision { uri, paths } => {
+                write!(f, "{} is generated by more than one source file:", uri)?;
+                for path in paths {
+                    write!(f, "\n    {}", path.display())?;
+                }
+                Ok(())
+            }
+            Self::Multiple(errors) => {
+                write!(f, "{} errors occurred", errors.len())?;
+                for error in errors {
+                    write!(f, "\n{}", error)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            Self::Config { source, .. } => Some(source),
+            Self::Template { source, .. } => Some(source),
+            Self::Watch { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn collect_every_error() {
+        let missing = |path: &str| Err::<(), _>(Error::Missing { path: PathBuf::from(path) });
+        let results = vec![missing("b.md"), Ok(()), missing("a.md")];
+        match collect(results) {
+            Err(Error::Multiple(errors)) => {
+                let paths: Vec<_> = errors.iter().map(|e| e.path().unwrap()).collect();
+                assert_eq!(paths, vec![Path::new("a.md"), Path::new("b.md")]);
+            }
+            _ => panic!("expected multiple errors"),
+        }
+        assert_eq!(collect(vec![Ok(1), Ok(2)]).unwrap(), vec![1, 2]);
+    }
+
+    #[test]
+    fn display_front_matter_location() {
+        let e = Error::FrontMatter {
+            path: PathBuf::from("src/index.md"),
+            line: Some(3),
+            column: Some(7),
+            message: "bad".to_string(),
+        };
+        assert_eq!(e.to_string(), "src/index.md:3:7: invalid front matter: bad");
+    }
+
+    #[test]
+    fn markdown_location_from_message() {
+        let e = Error::markdown("src/index.md", "2:5: Unexpected end of file".to_string());
+        assert_eq!(e.to_string(), "src/index.md:2:5: invalid markdown: Unexpected end of file");
+
+        let e = Error::markdown("src/index.md", "Unexpected: end of file".to_string());
+        assert_eq!(e.to_string(), "src/index.md: invalid markdown: Unexpected: end of file");
+    }
+
+    #[test]
+    fn display_without_location() {
+        let e = Error::Markdown {
+            path: PathBuf::from("src/index.md"),
+            line: None,
+            column: Some(7),
+            message: "bad".to_string(),
+        };
+        assert_eq!(e.to_string(), "src/index.md: invalid markdown: bad");
+    }
+}
@@ -0,0 +1,271 @@
+//! RSS and Atom feeds
+//!
+//! A section opts in to feeds with `feed: true` in the front matter of its
+//! `index.md`. Its pages, newest first, are then written to an RSS 2.0
+//! `feed.xml` and an Atom `atom.xml` alongside the section's `index.html`.
+//!
+//! Links in feeds must be absolute, so `base_url` should be set in the
+//! configuration. `feed_limit` caps the number of items, and `feed_content`
+//! chooses between each page's full content or just its description.
+
+use crate::config::{Config, FeedContent};
+use crate::page::{Page, PageMap};
+use crate::section::{Section, SectionMap};
+use crate::date::Date;
+use crate::cache::Cache;
+use crate::filesystem;
+use crate::error::Result;
+
+use std::fmt::Write;
+use log::warn;
+
+/// The file name of RSS feeds
+pub const RSS_FILE: &str = "feed.xml";
+/// The file name of Atom feeds
+pub const ATOM_FILE: &str = "atom.xml";
+
+/// Writes the RSS and Atom feeds of every section which opts in to them,
+/// returning how many sections have feeds
+///
+/// Sections whose index is a draft or scheduled, and so was removed, get
+/// no feeds.
+///
+/// # Arguments
+///
+/// * `config` - The site configuration
+/// * `sectionmap` - The sections of the site, with pages sorted newest first
+/// * `pagemap` - The pages of the site
+/// * `cache` - The build cache, which every file written is recorded in
+pub fn write_feeds(config: &Config, sectionmap: &SectionMap, pagemap: &PageMap, cache: &Cache) -> Result<usize> {
+    let sections: Vec<&Section> = sectionmap.0.values()
+        .filter(|section| section.feed && section.index.is_some())
+        .collect();
+    if !sections.is_empty() && config.base_url.is_empty() {
+        warn!("base_url is not set, so links in feeds will not be absolute");
+    }
+
+    for section in &sections {
+        let pages: Vec<&Page> = section.pages.iter()
+            .filter_map(|uri| pagemap.0.get(uri))
+            .take(config.feed_limit)
+            .collect();
+
+        let out_dir = section.uri.out_dir(&config.out_dir);
+        for (name, xml) in [(RSS_FILE, rss(config, section, &pages)), (ATOM_FILE, atom(config, section, &pages))] {
+            let path = out_dir.join(name);
+            filesystem::write(&path, xml.as_bytes())?;
+            cache.record(&path);
+        }
+    }
+    Ok(sections.len())
+}
+
+/// Renders the RSS 2.0 feed of `section`
+///
+/// # Arguments
+///
+/// * `config` - The site configuration
+/// * `section` - The section the feed is for
+/// * `pages` - The items of the feed, newest first
+pub fn rss(config: &Config, section: &Section, pages: &[&Page]) -> String {
+    let link = config.absolute_url(&section.uri.to_string());
+    let description = section.index.as_ref()
+        .and_then(|index| index.description.as_deref())
+        .or(config.description.as_deref())
+        .unwrap_or_default();
+
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\">\n");
+    xml.push_str("<channel>\n");
+    element(&mut xml, "title", &section.title);
+    element(&mut xml, "link", &link);
+    element(&mut xml, "description", description);
+    writeln!(xml, "<atom:link href=\"{}\" rel=\"self\" type=\"application/rss+xml\"/>",
+             escape(&feed_url(config, section, RSS_FILE))).unwrap();
+    if let Some(date) = pages.iter().find_map(|page| page.date) {
+        element(&mut xml, "lastBuildDate", &date.to_rfc2822());
+    }
+
+    for page in pages {
+        let link = config.absolute_url(&page.uri.to_string());
+        xml.push_str("<item>\n");
+        element(&mut xml, "title", &page.title);
+        element(&mut xml, "link", &link);
+        writeln!(xml, "<guid isPermaLink=\"true\">{}</guid>", escape(&link)).unwrap();
+        if let Some(date) = page.date {
+            element(&mut xml, "pubDate", &date.to_rfc2822());
+        }
+        if let Some(author) = page.author.as_deref().or(config.author.as_deref()) {
+            element(&mut xml, "author", author);
+        }
+        let description = match config.feed_content {
+            FeedContent::Full => Some(page.content.as_str()),
+            FeedContent::Summary => page.description.as_deref(),
+        };
+        if let Some(description) = description {
+            element(&mut xml, "description", description);
+        }
+        xml.push_str("</item>\n");
+    }
+
+    xml.push_str("</channel>\n");
+    xml.push_str("</rss>\n");
+    xml
+}
+
+/// Renders the Atom feed of `section`
+///
+/// # Arguments
+///
+/// * `config` - The site configuration
+/// * `section` - The section the feed is for
+/// * `pages` - The entries of the feed, newest first
+pub fn atom(config: &Config, section: &Section, pages: &[&Page]) -> String {
+    let self_url = feed_url(config, section, ATOM_FILE);
+    let updated = pages.iter()
+        .filter_map(|page| page.updated.or(page.date))
+        .max()
+        .unwrap_or_default();
+
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    element(&mut xml, "title", &section.title);
+    element(&mut xml, "id", &self_url);
+    element(&mut xml, "updated", &updated.to_rfc3339());
+    writeln!(xml, "<link href=\"{}\"/>",
+             escape(&config.absolute_url(&section.uri.to_string()))).unwrap();
+    writeln!(xml, "<link href=\"{}\" rel=\"self\"/>", escape(&self_url)).unwrap();
+    if let Some(author) = &config.author {
+        author_element(&mut xml, author);
+    }
+
+    for page in pages {
+        let link = config.absolute_url(&page.uri.to_string());
+        let updated: Date = page.updated.or(page.date).unwrap_or(updated);
+        xml.push_str("<entry>\n");
+        element(&mut xml, "title", &page.title);
+        element(&mut xml, "id", &link);
+        writeln!(xml, "<link href=\"{}\"/>", escape(&link)).unwrap();
+        element(&mut xml, "updated", &updated.to_rfc3339());
+        if let Some(date) = page.date {
+            element(&mut xml, "published", &date.to_rfc3339());
+        }
+        if let Some(author) = page.author.as_ref().or(config.author.as_ref()) {
+            author_element(&mut xml, author);
+        }
+        if let Some(description) = &page.description {
+            element(&mut xml, "summary", description);
+        }
+        if config.feed_content == FeedContent::Full {
+            writeln!(xml, "<content type=\"html\">{}</content>", escape(&page.content)).unwrap();
+        }
+        xml.push_str("</entry>\n");
+    }
+
+    xml.push_str("</feed>\n");
+    xml
+}
+
+/// Returns the absolute URL of the feed file `name` of `section`
+fn feed_url(config: &Config, section: &Section, name: &str) -> String {
+    let uri = section.uri.join(name)
+        .expect("feed file names are valid uri segments");
+    config.absolute_url(&uri.to_string())
+}
+
+/// Appends `<name>text</name>` to `xml`, escaping `text`
+fn element(xml: &mut String, name: &str, text: &str) {
+    writeln!(xml, "<{0}>{1}</{0}>", name, escape(text)).unwrap();
+}
+
+fn author_element(xml: &mut String, name: &str) {
+    xml.push_str("<author>");
+    writeln!(xml, "<name>{}</name></author>", escape(name)).unwrap();
+}
+
+/// Escapes the characters of `text` which are special in XML
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            ch => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::uri::Uri;
+    use crate::date;
+
+    fn config() -> Config {
+        Config {
+            base_url: "https://example.com/".to_string(),
+            author: Some("Alice".to_string()),
+            ..Config::default()
+        }
+    }
+
+    fn section() -> Section {
+        Section {
+            title: "Blog".to_string(),
+            feed: true,
+            ..Section::test(Uri::new().join("blog").unwrap())
+        }
+    }
+
+    fn page(name: &str, date: &str) -> Page {
+        Page {
+            title: format!("{} & more", name),
+            description: Some("A post".to_string()),
+            date: date::parse(date),
+            content: "<p>Hi</p>".to_string(),
+            ..Page::test(Uri::new().join("blog").unwrap().join(name).unwrap())
+        }
+    }
+
+    #[test]
+    fn escape_special_characters() {
+        assert_eq!(escape("<a href=\"x\">Tom & Jerry's</a>"),
+                   "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&apos;s&lt;/a&gt;");
+    }
+
+    #[test]
+    fn rss_items() {
+        let new = page("new", "2023-02-01");
+        let old = page("old", "2023-01-01");
+        let xml = rss(&config(), &section(), &[&new, &old]);
+
+        assert!(xml.contains("<link>https://example.com/blog</link>"));
+        assert!(xml.contains("href=\"https://example.com/blog/feed.xml\""));
+        assert!(xml.contains("<title>new &amp; more</title>"));
+        let pub_date = date::parse("2023-02-01").unwrap().to_rfc2822();
+        assert!(xml.contains(&format!("<pubDate>{}</pubDate>", pub_date)));
+        assert!(xml.contains("<author>Alice</author>"));
+        assert!(xml.contains("<description>&lt;p&gt;Hi&lt;/p&gt;</description>"));
+        assert!(xml.find("blog/new").unwrap() < xml.find("blog/old").unwrap());
+    }
+
+    #[test]
+    fn atom_summary_only() {
+        let config = Config { feed_content: FeedContent::Summary, ..config() };
+        let new = page("new", "2023-02-01");
+        let old = page("old", "2023-01-01");
+        let xml = atom(&config, &section(), &[&new, &old]);
+
+        assert!(xml.contains("<id>https://example.com/blog/atom.xml</id>"));
+        assert!(xml.contains("<updated>2023-02-01T00:00:00+00:00</updated>"));
+        assert!(xml.contains("<summary>A post</summary>"));
+        assert!(!xml.contains("<content"));
+        assert_eq!(xml.matches("<entry>").count(), 2);
+    }
+}
@@ -1,60 +1,65 @@
 //! Helper filesystem functions
 
+use crate::error::{Error, Result};
+
 use std::ffi::OsStr;
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::fs;
 
 /// Returns an iterator of the subdirectories of `dir`
-pub fn subdirs<P: AsRef<Path>>(dir: P) -> Result<impl Iterator<Item=PathBuf>, String> {
+pub fn subdirs<P: AsRef<Path>>(dir: P) -> Result<impl Iterator<Item=PathBuf>> {
     Ok(fs::read_dir(&dir)
-        .map_err(|e| e.to_string())?
+        .map_err(|e| Error::io(&dir, e))?
         .filter_map(|entry| entry.ok())
         .map(|entry| entry.path())
         .filter(|path| path.is_dir()))
 }
 
 /// Returns the file name of a path
-pub fn file_name<P: AsRef<Path>>(path: P) -> Result<String, String> {
+pub fn file_name<P: AsRef<Path>>(path: P) -> Result<String> {
     path
        .as_ref()
        .file_name()
-       .map(OsStr::to_str)
-       .flatten()
+       .and_then(OsStr::to_str)
        .map(String::from)
-       .ok_or("Unable to peek".to_string())
+       .ok_or_else(|| Error::Uri {
+           path: path.as_ref().to_owned(),
+           message: "file name is not valid UTF-8".to_string(),
+       })
 }
 
 /// Returns the file stem of a path
-pub fn file_stem<P: AsRef<Path>>(path: P) -> Result<String, String> {
+pub fn file_stem<P: AsRef<Path>>(path: P) -> Result<String> {
     path
        .as_ref()
        .file_stem()
-       .map(OsStr::to_str)
-       .flatten()
+       .and_then(OsStr::to_str)
        .map(String::from)
-       .ok_or("Unable to peek".to_string())
+       .ok_or_else(|| Error::Uri {
+           path: path.as_ref().to_owned(),
+           message: "file name is not valid UTF-8".to_string(),
+       })
 }
 
 // ugly ass fucking function signature
-pub fn files<P: AsRef<Path>>(dir: P) -> Result<impl Iterator<Item=PathBuf>, String> {
+pub fn files<P: AsRef<Path>>(dir: P) -> Result<impl Iterator<Item=PathBuf>> {
     Ok(fs::read_dir(dir.as_ref()) 
-        .map_err(|e| e.to_string())?
+        .map_err(|e| Error::io(&dir, e))?
         .filter_map(|entry| entry.ok())
         .map(|entry| entry.path())
         .filter(|path| path.is_file()))
 }
 
-pub fn has_file<P: AsRef<Path>>(dir: P, filename: &str) -> Result<bool, String> {
+pub fn has_file<P: AsRef<Path>>(dir: P, filename: &str) -> Result<bool> {
     Ok(files(dir)?.any(|file|
         file.file_name()
-            .map(|osstr| osstr.to_str())
-            .flatten()
+            .and_then(|osstr| osstr.to_str())
         == Some(filename)
     ))
 }
 
-pub fn files_with_extension<P: AsRef<Path>>(dir: P, ext: &'static str) -> Result<impl Iterator<Item=PathBuf>, String> {
+pub fn files_with_extension<P: AsRef<Path>>(dir: P, ext: &'static str) -> Result<impl Iterator<Item=PathBuf>> {
     files(dir)
         .map(|iter| iter
              .filter(|path| path.extension()
@@ -62,16 +67,143 @@ pub fn files_with_extension<P: AsRef<Path>>(dir: P, ext: &'static str) -> Result
         )
 }
 
-pub fn cat<P>(path: P, text: &[u8]) -> std::io::Result<()>
+/// The files mirrored by `copy_dir`
+#[derive(Debug, Default)]
+pub struct Mirrored {
+    /// Every file in the destination with a counterpart in the source
+    pub files: Vec<PathBuf>,
+    /// How many of those were copied, rather than already up to date
+    pub copied: usize,
+}
+
+/// Recursively copies the files in `src` into `dest`
+///
+/// Files whose copy in `dest` already has the same contents are skipped.
+/// Out of date copies are removed before copying, so that copies of
+/// read-only files, which keep their permissions, can still be replaced.
+pub fn copy_dir<P, Q>(src: P, dest: Q) -> Result<Mirrored>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>
+{
+    let (src, dest) = (src.as_ref(), dest.as_ref());
+    fs::create_dir_all(dest)
+        .map_err(|e| Error::io(dest, e))?;
+
+    let mut mirrored = Mirrored::default();
+    for path in files(src)? {
+        let target = dest.join(file_name(&path)?);
+        if !same_contents(&path, &target)? {
+            match fs::remove_file(&target) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(Error::io(&target, e)),
+                _ => {}
+            }
+            fs::copy(&path, &target)
+                .map_err(|e| Error::io(&target, e))?;
+            mirrored.copied += 1;
+        }
+        mirrored.files.push(target);
+    }
+
+    for subdir in subdirs(src)? {
+        let sub = copy_dir(&subdir, dest.join(file_name(&subdir)?))?;
+        mirrored.files.extend(sub.files);
+        mirrored.copied += sub.copied;
+    }
+    Ok(mirrored)
+}
+
+/// Whether the file at `target` exists and has the same contents as the
+/// file at `path`
+fn same_contents(path: &Path, target: &Path) -> Result<bool> {
+    let len = |path: &Path| fs::metadata(path).map(|metadata| metadata.len());
+    match len(target) {
+        Ok(target_len) if target_len == len(path).map_err(|e| Error::io(path, e))? => {}
+        _ => return Ok(false),
+    }
+    let read = |path: &Path| fs::read(path).map_err(|e| Error::io(path, e));
+    Ok(read(path)? == read(target)?)
+}
+
+/// Writes `text` to the file at `path`, replacing any previous contents
+pub fn write<P>(path: P, text: &[u8]) -> Result<()>
+where
+    P: AsRef<Path>
+{
+    fs::write(&path, text)
+        .map_err(|e| Error::io(path, e))
+}
+
+pub fn cat<P>(path: P, text: &[u8]) -> Result<()>
 where
     P: AsRef<Path>
 {
     fs::OpenOptions::new()
-        .write(true)
         .append(true)
         .create(true)
-        .open(path)?
-        .write_all(text)
+        .open(&path)
+        .and_then(|mut file| file.write_all(text))
+        .map_err(|e| Error::io(path, e))
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn copy_dir_skips_unchanged_files() {
+        let dir = std::env::temp_dir().join("j3sg-test-copy-dir");
+        fs::remove_dir_all(&dir).unwrap_or(());
+        let (src, dest) = (dir.join("static"), dir.join("public/static"));
+        fs::create_dir_all(src.join("css")).unwrap();
+        fs::write(src.join("favicon.ico"), "icon").unwrap();
+        fs::write(src.join("css/site.css"), "body {}").unwrap();
+
+        let mirrored = copy_dir(&src, &dest).unwrap();
+        assert_eq!((mirrored.files.len(), mirrored.copied), (2, 2));
+        assert_eq!(fs::read_to_string(dest.join("css/site.css")).unwrap(), "body {}");
+        assert_eq!(copy_dir(&src, &dest).unwrap().copied, 0);
+
+        fs::write(src.join("css/site.css"), "body { margin: 0 }").unwrap();
+        let mirrored = copy_dir(&src, &dest).unwrap();
+        assert_eq!((mirrored.files.len(), mirrored.copied), (2, 1));
+        assert_eq!(fs::read_to_string(dest.join("css/site.css")).unwrap(), "body { margin: 0 }");
+    }
+
+    #[test]
+    fn copy_dir_replaces_read_only_copies() {
+        let dir = std::env::temp_dir().join("j3sg-test-copy-read-only");
+        fs::remove_dir_all(&dir).unwrap_or(());
+        let (src, dest) = (dir.join("static"), dir.join("public/static"));
+        let path = src.join("robots.txt");
+        let set_readonly = |path: &Path, readonly| {
+            let mut permissions = fs::metadata(path).unwrap().permissions();
+            permissions.set_readonly(readonly);
+            fs::set_permissions(path, permissions).unwrap();
+        };
+        fs::create_dir_all(&src).unwrap();
+        fs::write(&path, "old").unwrap();
+        set_readonly(&path, true);
+        copy_dir(&src, &dest).unwrap();
+
+        set_readonly(&path, false);
+        fs::write(&path, "new").unwrap();
+        set_readonly(&path, true);
+        assert_eq!(copy_dir(&src, &dest).unwrap().copied, 1);
+        assert_eq!(fs::read_to_string(dest.join("robots.txt")).unwrap(), "new");
+
+        set_readonly(&path, false);
+        set_readonly(&dest.join("robots.txt"), false);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn write_replaces_contents() {
+        let path = std::env::temp_dir().join("j3sg-test-write.xml");
+        write(&path, b"<old/>").unwrap();
+        write(&path, b"<new/>").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "<new/>");
+    }
 }
 
 /*
@@ -0,0 +1,229 @@
+//! Syntax highlighting of fenced code blocks
+//!
+//! Code is highlighted at build time with [syntect], using inline styles
+//! from the theme named by `highlight_theme`, so no stylesheet is needed.
+//! The info string of a fence may also ask for line numbers and for lines
+//! to be highlighted, e.g
+//!
+//! ````text
+//! ```rust linenos hl_lines=2-3,5
+//! ````
+//!
+//! * `linenos` - Number every line
+//! * `linenostart=N` - Number lines starting from `N` rather than 1
+//! * `hl_lines=RANGES` - Highlight the given lines, counting from 1, as a
+//!   comma separated list of line numbers and ranges such as `2-3`
+//!
+//! [syntect]: https://github.com/trishume/syntect
+
+use crate::html::escape;
+
+use std::fmt::Write;
+use std::ops::RangeInclusive;
+use std::sync::OnceLock;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Color, Style, Theme, ThemeSet};
+use syntect::html::{append_highlighted_html_for_styled_line, IncludeBackground};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+
+/// The theme used when none is configured
+pub const DEFAULT_THEME: &str = "InspiredGitHub";
+
+fn syntaxes() -> &'static SyntaxSet {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+fn themes() -> &'static ThemeSet {
+    static THEMES: OnceLock<ThemeSet> = OnceLock::new();
+    THEMES.get_or_init(ThemeSet::load_defaults)
+}
+
+/// Returns the names of every available theme, in order
+pub fn theme_names() -> Vec<&'static str> {
+    themes().themes.keys().map(String::as_str).collect()
+}
+
+/// How a code block should be displayed, as given in its fence's info string
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Fence {
+    /// The number of the first line, if lines are numbered
+    pub linenos: Option<usize>,
+    /// The lines to highlight, counting from 1
+    pub hl_lines: Vec<RangeInclusive<usize>>,
+}
+
+impl Fence {
+    /// Parses the words of a fence's info string which follow the language
+    ///
+    /// Unrecognized words are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `meta` - The info string, without the language
+    pub fn parse(meta: &str) -> Self {
+        let mut fence = Self::default();
+        let mut start = None;
+        for word in meta.split_whitespace() {
+            match word.split_once('=') {
+                None if word == "linenos" => {
+                    fence.linenos.get_or_insert(1);
+                }
+                Some(("linenostart", n)) => start = n.parse().ok(),
+                Some(("hl_lines", ranges)) => {
+                    fence.hl_lines.extend(ranges.split(',').filter_map(parse_range));
+                }
+                _ => {}
+            }
+        }
+        if let (Some(linenos), Some(start)) = (&mut fence.linenos, start) {
+            *linenos = start;
+        }
+        fence
+    }
+
+    fn is_highlighted(&self, line: usize) -> bool {
+        self.hl_lines.iter().any(|range| range.contains(&line))
+    }
+}
+
+/// Parses a line number, or an inclusive range of them such as `2-3`
+fn parse_range(range: &str) -> Option<RangeInclusive<usize>> {
+    match range.split_once('-') {
+        Some((start, end)) => Some(start.trim().parse().ok()?..=end.trim().parse().ok()?),
+        None => {
+            let line = range.trim().parse().ok()?;
+            Some(line..=line)
+        }
+    }
+}
+
+/// Renders `code` as a highlighted `<pre>` block, or returns `None` if
+/// `theme` does not exist
+///
+/// Code in a language which is not recognized is rendered as plain text,
+/// still with any line numbers and highlighted lines.
+///
+/// # Arguments
+///
+/// * `code` - The contents of the code block
+/// * `lang` - The language of the code block, if given
+/// * `meta` - The rest of the fence's info string, if any
+/// * `theme` - The name of the theme to highlight with
+pub fn highlight(code: &str, lang: Option<&str>, meta: Option<&str>, theme: &str) -> Option<String> {
+    let theme = themes().themes.get(theme)?;
+    let syntaxes = syntaxes();
+    let syntax = lang
+        .and_then(|lang| syntaxes.find_syntax_by_token(lang))
+        .unwrap_or_else(|| syntaxes.find_syntax_plain_text());
+    let fence = meta.map(Fence::parse).unwrap_or_default();
+
+    let mut html = String::new();
+    write!(html, "<pre class=\"highlight\"{}>", style("background-color", theme.settings.background)).unwrap();
+    match lang {
+        Some(lang) => write!(html, "<code class=\"language-{}\">", escape(lang)).unwrap(),
+        None => html.push_str("<code>"),
+    }
+
+    let mut highlighter = HighlightLines::new(syntax, theme);
+    for (i, line) in LinesWithEndings::from(code).enumerate() {
+        let styled = highlighter.highlight_line(line, syntaxes).ok()?;
+        write_line(&mut html, &fence, theme, i + 1, &styled);
+    }
+    if !code.is_empty() && !code.ends_with('\n') {
+        html.push('\n');
+    }
+
+    html.push_str("</code></pre>\n");
+    Some(html)
+}
+
+/// Appends a single highlighted line of a code block to `html`
+fn write_line(
+    html: &mut String,
+    fence: &Fence,
+    theme: &Theme,
+    number: usize,
+    styled: &[(Style, &str)]
+) {
+    if fence.is_highlighted(number) {
+        // Highlighted lines stretch to the full width of the block
+        let background = theme.settings.line_highlight
+            .map(|Color { r, g, b, .. }| format!("background-color:#{:02x}{:02x}{:02x};", r, g, b))
+            .unwrap_or_default();
+        write!(html, "<span class=\"line hl\" style=\"display:inline-block;min-width:100%;{}\">", background).unwrap();
+    } else {
+        html.push_str("<span class=\"line\">");
+    }
+    if let Some(start) = fence.linenos {
+        write!(
+            html,
+            "<span class=\"lineno\" style=\"user-select:none;opacity:0.6;padding-right:1em;\">{}</span>",
+            start + number - 1,
+        ).unwrap();
+    }
+
+    // The newline goes outside the span, so that highlighted lines can be
+    // styled as blocks without doubling up on line breaks
+    let (styled, newline) = match styled.split_last() {
+        Some(((style, text), rest)) if text.ends_with('\n') => {
+            let mut styled = rest.to_vec();
+            styled.push((*style, text.trim_end_matches('\n')));
+            (styled, "\n")
+        }
+        _ => (styled.to_vec(), ""),
+    };
+    // Only fails if writing to a String fails, which it does not
+    let _ = append_highlighted_html_for_styled_line(&styled, IncludeBackground::No, html);
+    html.push_str("</span>");
+    html.push_str(newline);
+}
+
+/// Returns a ` style="..."` attribute setting `property` to `color`,
+/// or nothing if there is no color
+fn style(property: &str, color: Option<Color>) -> String {
+    match color {
+        Some(Color { r, g, b, .. }) => format!(" style=\"{}:#{:02x}{:02x}{:02x};\"", property, r, g, b),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn parse_fence() {
+        assert_eq!(Fence::parse(""), Fence::default());
+        assert_eq!(Fence::parse("linenos hl_lines=2-3,5 title=\"x\""), Fence {
+            linenos: Some(1),
+            hl_lines: vec![2..=3, 5..=5],
+        });
+        assert_eq!(Fence::parse("linenostart=10 linenos").linenos, Some(10));
+        assert_eq!(Fence::parse("linenostart=10").linenos, None);
+    }
+
+    #[test]
+    fn highlight_code() {
+        let html = highlight("fn main() {}\n// hi", Some("rust"), None, DEFAULT_THEME).unwrap();
+        assert!(html.starts_with("<pre class=\"highlight\" style=\"background-color:"));
+        assert!(html.contains("<code class=\"language-rust\">"));
+        assert!(html.contains("<span style=\"color:"));
+        assert_eq!(html.matches("<span class=\"line\">").count(), 2);
+    }
+
+    #[test]
+    fn line_numbers_and_highlighted_lines() {
+        let html = highlight("a\nb\nc", None, Some("linenos linenostart=7 hl_lines=2"), DEFAULT_THEME).unwrap();
+        assert!(html.contains("<code>"));
+        assert!(html.contains(">7</span><span style=\"color:#323232;\">a</span></span>\n"));
+        assert!(html.contains("<span class=\"line hl\""));
+        assert!(html.contains(">9</span>"));
+    }
+
+    #[test]
+    fn unknown_theme() {
+        assert!(highlight("a", None, None, "no such theme").is_none());
+    }
+}
@@ -0,0 +1,682 @@
+//! Rendering markdown syntax trees to HTML
+//!
+//! j3sg renders the syntax tree produced by markdown-rs itself, rather than
+//! letting markdown-rs compile straight to HTML, so that it can see things
+//! markdown-rs does not put in its HTML, such as the rest of a code fence's
+//! info string, and so that it can give headings ids and rewrite links.
+//! Otherwise its output is the same as markdown-rs', which the tests check.
+
+use crate::highlight;
+use crate::parse::MarkdownOptions;
+use crate::slug::slugify;
+use crate::toc::{self, TocEntry};
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use markdown::mdast::{
+    AlignKind, Code, Definition, FootnoteDefinition, List, ListItem, Node, Table,
+};
+use markdown::unist::Position;
+
+/// Protocols links may use, any other link is dropped
+const SAFE_PROTOCOLS: [&str; 6] = ["http", "https", "irc", "ircs", "mailto", "xmpp"];
+
+/// Rewrites the URL of a link on the given line, or returns `None` to leave
+/// it as is
+pub type Resolve<'a> = dyn FnMut(&str, Option<usize>) -> Option<String> + 'a;
+
+/// Renders the markdown syntax tree `root` to HTML, along with the table
+/// of contents of its headings
+///
+/// # Arguments
+///
+/// * `root` - The syntax tree of a whole document
+/// * `options` - The markdown options the tree was parsed with
+/// * `resolve` - Rewrites the URLs of links
+pub fn to_html<'a>(
+    root: &'a Node,
+    options: &'a MarkdownOptions,
+    resolve: &'a mut Resolve<'_>
+) -> (String, Vec<TocEntry>) {
+    let mut writer = Writer {
+        html: String::new(),
+        options,
+        resolve,
+        definitions: HashMap::new(),
+        footnotes: HashMap::new(),
+        footnote_order: Vec::new(),
+        footnote_refs: HashMap::new(),
+        // The footnotes heading is always given this id
+        ids: HashMap::from([("footnote-label".to_string(), 0)]),
+        headings: Vec::new(),
+    };
+    writer.collect(root);
+    writer.node(root);
+    writer.footnote_section();
+    (writer.html, toc::build(writer.headings))
+}
+
+struct Writer<'a> {
+    html: String,
+    options: &'a MarkdownOptions,
+    resolve: &'a mut Resolve<'a>,
+    /// Link reference definitions, by identifier
+    definitions: HashMap<&'a str, &'a Definition>,
+    /// Footnote definitions, by identifier
+    footnotes: HashMap<&'a str, &'a FootnoteDefinition>,
+    /// The identifiers of footnotes, in the order they are first referenced
+    footnote_order: Vec<&'a str>,
+    /// How many times each footnote has been referenced so far
+    footnote_refs: HashMap<&'a str, usize>,
+    /// How many headings have been given each slug as their id so far,
+    /// less one
+    ids: HashMap<String, usize>,
+    /// The level, text and id of every heading so far
+    headings: Vec<(u8, String, String)>,
+}
+
+impl<'a> Writer<'a> {
+    /// Gathers every definition in the tree, since they may be used before
+    /// they are defined
+    fn collect(&mut self, node: &'a Node) {
+        match node {
+            Node::Definition(definition) => {
+                self.definitions.entry(&definition.identifier).or_insert(definition);
+            }
+            Node::FootnoteDefinition(definition) => {
+                self.footnotes.entry(&definition.identifier).or_insert(definition);
+            }
+            _ => {}
+        }
+        for child in node.children().into_iter().flatten() {
+            self.collect(child);
+        }
+    }
+
+    fn children(&mut self, children: &'a [Node]) {
+        for child in children {
+            self.node(child);
+        }
+    }
+
+    /// Renders block children, each on its own line
+    fn blocks(&mut self, children: &'a [Node]) {
+        for child in children {
+            self.line_ending_if_needed();
+            self.node(child);
+        }
+    }
+
+    fn line_ending_if_needed(&mut self) {
+        if !self.html.is_empty() && !self.html.ends_with('\n') {
+            self.html.push('\n');
+        }
+    }
+
+    fn node(&mut self, node: &'a Node) {
+        match node {
+            Node::Root(root) => self.blocks(&root.children),
+            Node::Paragraph(paragraph) => {
+                self.html.push_str("<p>");
+                self.children(&paragraph.children);
+                self.html.push_str("</p>\n");
+            }
+            Node::Heading(heading) => {
+                let text = plain_text(&heading.children);
+                let id = self.unique_id(&text);
+                write!(self.html, "<h{} id=\"{}\">", heading.depth, escape(&id)).unwrap();
+                self.children(&heading.children);
+                if self.options.heading_anchors {
+                    write!(
+                        self.html,
+                        " <a class=\"anchor\" href=\"#{}\" aria-hidden=\"true\">#</a>",
+                        escape(&id),
+                    ).unwrap();
+                }
+                writeln!(self.html, "</h{}>", heading.depth).unwrap();
+                self.headings.push((heading.depth, text, id));
+            }
+            Node::ThematicBreak(_) => self.html.push_str("<hr />\n"),
+            Node::BlockQuote(quote) => {
+                self.html.push_str("<blockquote>\n");
+                self.blocks(&quote.children);
+                self.line_ending_if_needed();
+                self.html.push_str("</blockquote>\n");
+            }
+            Node::List(list) => self.list(list),
+            Node::Code(code) => self.code(code),
+            Node::Math(math) => {
+                self.html.push_str("<pre><code class=\"language-math math-display\">");
+                self.text(&math.value);
+                if !math.value.is_empty() {
+                    self.html.push('\n');
+                }
+                self.html.push_str("</code></pre>\n");
+            }
+            Node::Html(html) => {
+                if self.options.html {
+                    self.html.push_str(&html.value);
+                } else {
+                    self.text(&html.value);
+                }
+            }
+            Node::Table(table) => self.table(table),
+            Node::Text(text) => self.text(&text.value),
+            Node::Emphasis(emphasis) => self.wrap("em", &emphasis.children),
+            Node::Strong(strong) => self.wrap("strong", &strong.children),
+            Node::Delete(delete) => self.wrap("del", &delete.children),
+            Node::InlineCode(code) => {
+                self.html.push_str("<code>");
+                self.text(&inline_code(&code.value));
+                self.html.push_str("</code>");
+            }
+            Node::InlineMath(math) => {
+                self.html.push_str("<code class=\"language-math math-inline\">");
+                self.text(&math.value);
+                self.html.push_str("</code>");
+            }
+            Node::Break(_) => self.html.push_str("<br />\n"),
+            Node::Link(link) => {
+                self.link_start(&link.url, link.title.as_deref(), line(&link.position));
+                self.children(&link.children);
+                self.html.push_str("</a>");
+            }
+            Node::LinkReference(reference) => {
+                match self.definitions.get(reference.identifier.as_str()) {
+                    Some(definition) => {
+                        self.link_start(&definition.url, definition.title.as_deref(), line(&definition.position));
+                        self.children(&reference.children);
+                        self.html.push_str("</a>");
+                    }
+                    None => self.children(&reference.children),
+                }
+            }
+            Node::Image(image) => self.image(&image.url, &image.alt, image.title.as_deref()),
+            Node::ImageReference(reference) => {
+                match self.definitions.get(reference.identifier.as_str()) {
+                    Some(definition) => {
+                        self.image(&definition.url, &reference.alt, definition.title.as_deref());
+                    }
+                    None => self.text(&reference.alt),
+                }
+            }
+            Node::FootnoteReference(reference) => self.footnote_reference(&reference.identifier),
+            // Definitions are only rendered where they are used, and front
+            // matter is not rendered at all
+            Node::Definition(_)
+            | Node::FootnoteDefinition(_)
+            | Node::Yaml(_)
+            | Node::Toml(_) => {}
+            // MDX is never enabled
+            Node::MdxjsEsm(_)
+            | Node::MdxFlowExpression(_)
+            | Node::MdxTextExpression(_)
+            | Node::MdxJsxFlowElement(_)
+            | Node::MdxJsxTextElement(_) => {}
+            // These only appear inside the nodes handling them
+            Node::ListItem(_) | Node::TableRow(_) | Node::TableCell(_) => {}
+        }
+    }
+
+    /// Returns the slug of `text` as an id, with a number appended if an
+    /// earlier heading already has it, e.g `setup`, `setup-1`, `setup-2`
+    fn unique_id(&mut self, text: &str) -> String {
+        let mut slug = slugify(text);
+        if slug.is_empty() {
+            slug = "section".to_string();
+        }
+        loop {
+            let id = match self.ids.get_mut(&slug) {
+                None => slug.clone(),
+                Some(count) => {
+                    *count += 1;
+                    format!("{}-{}", slug, count)
+                }
+            };
+            // The numbered id may itself be the slug of another heading
+            if id == slug || !self.ids.contains_key(&id) {
+                self.ids.insert(id.clone(), 0);
+                return id;
+            }
+        }
+    }
+
+    fn wrap(&mut self, tag: &str, children: &'a [Node]) {
+        write!(self.html, "<{}>", tag).unwrap();
+        self.children(children);
+        write!(self.html, "</{}>", tag).unwrap();
+    }
+
+    fn text(&mut self, text: &str) {
+        self.html.push_str(&escape(text));
+    }
+
+    fn list(&mut self, list: &'a List) {
+        let tag = if list.ordered { "ol" } else { "ul" };
+        match list.start {
+            Some(start) if list.ordered && start != 1 => {
+                writeln!(self.html, "<ol start=\"{}\">", start).unwrap();
+            }
+            _ => writeln!(self.html, "<{}>", tag).unwrap(),
+        }
+
+        let loose = list.spread || list.children.iter()
+            .any(|child| matches!(child, Node::ListItem(item) if item.spread));
+        for child in &list.children {
+            if let Node::ListItem(item) = child {
+                self.list_item(item, loose);
+            }
+        }
+        writeln!(self.html, "</{}>", tag).unwrap();
+    }
+
+    /// Renders a list item. In a tight list, the paragraphs of each item are
+    /// not wrapped in `<p>` tags.
+    fn list_item(&mut self, item: &'a ListItem, loose: bool) {
+        self.html.push_str("<li>");
+        if let Some(checked) = item.checked {
+            let checked = if checked { "checked=\"\" " } else { "" };
+            write!(self.html, "<input type=\"checkbox\" disabled=\"\" {}/> ", checked).unwrap();
+        }
+
+        for (i, child) in item.children.iter().enumerate() {
+            match child {
+                Node::Paragraph(paragraph) if !loose => {
+                    if i > 0 {
+                        self.line_ending_if_needed();
+                    }
+                    self.children(&paragraph.children);
+                }
+                _ => {
+                    self.line_ending_if_needed();
+                    self.node(child);
+                }
+            }
+        }
+        if loose && !item.children.is_empty() {
+            self.line_ending_if_needed();
+        }
+        self.html.push_str("</li>\n");
+    }
+
+    fn code(&mut self, code: &'a Code) {
+        if self.options.highlight {
+            let highlighted = highlight::highlight(
+                &code.value,
+                code.lang.as_deref(),
+                code.meta.as_deref(),
+                &self.options.highlight_theme,
+            );
+            if let Some(highlighted) = highlighted {
+                self.html.push_str(&highlighted);
+                return;
+            }
+        }
+
+        match &code.lang {
+            Some(lang) => write!(self.html, "<pre><code class=\"language-{}\">", escape(lang)).unwrap(),
+            None => self.html.push_str("<pre><code>"),
+        }
+        self.text(&code.value);
+        if !code.value.is_empty() {
+            self.html.push('\n');
+        }
+        self.html.push_str("</code></pre>\n");
+    }
+
+    fn table(&mut self, table: &'a Table) {
+        self.html.push_str("<table>\n");
+        for (i, row) in table.children.iter().enumerate() {
+            let Node::TableRow(row) = row else { continue };
+            let cell_tag = if i == 0 { "th" } else { "td" };
+            match i {
+                0 => self.html.push_str("<thead>\n"),
+                1 => self.html.push_str("<tbody>\n"),
+                _ => {}
+            }
+
+            // Rows have as many cells as the header, extra cells are dropped
+            // and missing ones left empty
+            self.html.push_str("<tr>\n");
+            for (j, align) in table.align.iter().enumerate() {
+                let align = match align {
+                    AlignKind::Left => " align=\"left\"",
+                    AlignKind::Right => " align=\"right\"",
+                    AlignKind::Center => " align=\"center\"",
+                    AlignKind::None => "",
+                };
+                write!(self.html, "<{}{}>", cell_tag, align).unwrap();
+                if let Some(Node::TableCell(cell)) = row.children.get(j) {
+                    self.children(&cell.children);
+                }
+                writeln!(self.html, "</{}>", cell_tag).unwrap();
+            }
+            self.html.push_str("</tr>\n");
+
+            if i == 0 {
+                self.html.push_str("</thead>\n");
+            }
+        }
+        if table.children.len() > 1 {
+            self.html.push_str("</tbody>\n");
+        }
+        self.html.push_str("</table>\n");
+    }
+
+    fn link_start(&mut self, url: &str, title: Option<&str>, line: Option<usize>) {
+        let url = (self.resolve)(url, line).unwrap_or_else(|| url.to_string());
+        write!(self.html, "<a href=\"{}\"", escape(&sanitize_url(&url))).unwrap();
+        if let Some(title) = title {
+            write!(self.html, " title=\"{}\"", escape(title)).unwrap();
+        }
+        self.html.push('>');
+    }
+
+    fn image(&mut self, url: &str, alt: &str, title: Option<&str>) {
+        write!(self.html, "<img src=\"{}\" alt=\"{}\"", escape(&sanitize_url(url)), escape(alt)).unwrap();
+        if let Some(title) = title {
+            write!(self.html, " title=\"{}\"", escape(title)).unwrap();
+        }
+        self.html.push_str(" />");
+    }
+
+    fn footnote_reference(&mut self, identifier: &'a str) {
+        if !self.footnotes.contains_key(identifier) {
+            write!(self.html, "[^{}]", escape(identifier)).unwrap();
+            return;
+        }
+
+        let number = match self.footnote_order.iter().position(|id| *id == identifier) {
+            Some(i) => i + 1,
+            None => {
+                self.footnote_order.push(identifier);
+                self.footnote_order.len()
+            }
+        };
+        let count = self.footnote_refs.entry(identifier).or_insert(0);
+        *count += 1;
+
+        write!(
+            self.html,
+            "<sup><a href=\"#{}\" id=\"{}\" data-footnote-ref=\"\" \
+             aria-describedby=\"footnote-label\">{}</a></sup>",
+            footnote_id(identifier), footnote_ref_id(identifier, *count), number,
+        ).unwrap();
+    }
+
+    /// Renders the definitions of every referenced footnote, in the order
+    /// they were first referenced
+    fn footnote_section(&mut self) {
+        if self.footnote_order.is_empty() {
+            return;
+        }
+
+        self.line_ending_if_needed();
+        self.html.push_str("<section data-footnotes=\"\" class=\"footnotes\">\
+                            <h2 id=\"footnote-label\" class=\"sr-only\">Footnotes</h2>\n<ol>\n");
+
+        // Rendering a definition may reference footnotes not seen before
+        let mut i = 0;
+        while i < self.footnote_order.len() {
+            let identifier = self.footnote_order[i];
+            let definition = self.footnotes[identifier];
+            let backrefs: String = (1..=self.footnote_refs[identifier])
+                .map(|count| format!(
+                    " <a href=\"#{}\" data-footnote-backref=\"\" \
+                     aria-label=\"Back to content\" class=\"data-footnote-backref\">↩{}</a>",
+                    footnote_ref_id(identifier, count),
+                    if count == 1 { String::new() } else { format!("<sup>{}</sup>", count) },
+                ))
+                .collect();
+
+            writeln!(self.html, "<li id=\"{}\">", footnote_id(identifier)).unwrap();
+            self.blocks(&definition.children);
+            // The links back go at the end of the last paragraph
+            if self.html.ends_with("</p>\n")
+                && matches!(definition.children.last(), Some(Node::Paragraph(_)))
+            {
+                self.html.truncate(self.html.len() - "</p>\n".len());
+                self.html.push_str(&backrefs);
+                self.html.push_str("</p>\n");
+            } else {
+                self.line_ending_if_needed();
+                self.html.push_str(backrefs.trim_start());
+                self.html.push('\n');
+            }
+            self.html.push_str("</li>\n");
+            i += 1;
+        }
+        self.html.push_str("</ol>\n</section>\n");
+    }
+}
+
+/// Returns the text of `nodes`, without any markup
+fn plain_text(nodes: &[Node]) -> String {
+    let mut text = String::new();
+    for node in nodes {
+        match node {
+            Node::Text(node) => text.push_str(&node.value),
+            Node::InlineCode(node) => text.push_str(&inline_code(&node.value)),
+            Node::InlineMath(node) => text.push_str(&node.value),
+            Node::Image(node) => text.push_str(&node.alt),
+            Node::ImageReference(node) => text.push_str(&node.alt),
+            Node::Break(_) => text.push(' '),
+            node => text.push_str(&plain_text(node.children().map_or(&[], Vec::as_slice))),
+        }
+    }
+    text
+}
+
+/// Returns the line a node starts on, if known
+fn line(position: &Option<Position>) -> Option<usize> {
+    position.as_ref().map(|position| position.start.line)
+}
+
+/// Returns the text of an inline code span as shown: line endings become
+/// spaces, and a single space is stripped from both ends of text which
+/// starts and ends with one, so that code can start or end with a backtick
+fn inline_code(value: &str) -> String {
+    let value = value.replace(['\r', '\n'], " ");
+    match value.strip_prefix(' ').and_then(|value| value.strip_suffix(' ')) {
+        Some(stripped) if !stripped.trim_start_matches(' ').is_empty() => stripped.to_string(),
+        _ => value,
+    }
+}
+
+/// Returns the id of the definition of the footnote `identifier`
+///
+/// Like markdown-rs, ids are prefixed with `user-content-` so that they
+/// cannot clobber ids used by the rest of the page.
+fn footnote_id(identifier: &str) -> String {
+    format!("user-content-fn-{}", escape(&percent_encode(&identifier.to_lowercase())))
+}
+
+/// Returns the id of the `count`th reference to the footnote `identifier`
+fn footnote_ref_id(identifier: &str, count: usize) -> String {
+    let id = format!("user-content-fnref-{}", escape(&percent_encode(&identifier.to_lowercase())));
+    if count == 1 {
+        id
+    } else {
+        format!("{}-{}", id, count)
+    }
+}
+
+/// Escapes the characters of `text` which are special in HTML
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            ch => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+/// Makes `url` safe to put in a link: links with a protocol other than
+/// those in `SAFE_PROTOCOLS`, e.g `javascript:`, are dropped, and characters
+/// which may not appear in URLs are percent-encoded
+pub fn sanitize_url(url: &str) -> String {
+    let protocol_end = url.find([':', '/', '?', '#']);
+    if let Some(end) = protocol_end.filter(|&end| url[end..].starts_with(':')) {
+        let protocol = url[..end].to_ascii_lowercase();
+        if !SAFE_PROTOCOLS.contains(&protocol.as_str()) {
+            return String::new();
+        }
+    }
+    percent_encode(url)
+}
+
+/// Percent-encodes the characters of `url` which may not appear in URLs,
+/// leaving existing escapes as they are
+fn percent_encode(url: &str) -> String {
+    let mut sanitized = String::with_capacity(url.len());
+    let bytes = url.as_bytes();
+    for (i, &byte) in bytes.iter().enumerate() {
+        let is_escape = byte == b'%'
+            && bytes.get(i + 1).is_some_and(u8::is_ascii_hexdigit)
+            && bytes.get(i + 2).is_some_and(u8::is_ascii_hexdigit);
+        if byte.is_ascii_alphanumeric() || b"!#$&'()*+,-./:;=?@_~".contains(&byte) || is_escape {
+            sanitized.push(byte as char);
+        } else {
+            write!(sanitized, "%{:02X}", byte).unwrap();
+        }
+    }
+    sanitized
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    fn render(text: &str) -> String {
+        render_with(text, &MarkdownOptions { highlight: false, ..Default::default() })
+    }
+
+    fn render_with(text: &str, options: &MarkdownOptions) -> String {
+        let root = markdown::to_mdast(text, &options.parse_options()).unwrap();
+        to_html(&root, options, &mut |_, _| None).0
+    }
+
+    #[test]
+    fn blocks_and_inlines() {
+        assert_eq!(render("# Hi *there*\n\nA [link](/a \"t\") and `code`"),
+                   "<h1 id=\"hi-there\">Hi <em>there</em></h1>\n<p>A <a href=\"/a\" title=\"t\">link</a> and <code>code</code></p>\n");
+        assert_eq!(render("> quote\n\n---"),
+                   "<blockquote>\n<p>quote</p>\n</blockquote>\n<hr />\n");
+        assert_eq!(render("```rust\nlet x = 1 < 2;\n```"),
+                   "<pre><code class=\"language-rust\">let x = 1 &lt; 2;\n</code></pre>\n");
+    }
+
+    #[test]
+    fn heading_ids() {
+        assert_eq!(render("## A *b* `c`\n\n## A b c\n\n## A b c 1\n\n## !"),
+                   "<h2 id=\"a-b-c\">A <em>b</em> <code>c</code></h2>\n<h2 id=\"a-b-c-1\">A b c</h2>\n\
+                    <h2 id=\"a-b-c-1-1\">A b c 1</h2>\n<h2 id=\"section\">!</h2>\n");
+    }
+
+    #[test]
+    fn tight_and_loose_lists() {
+        assert_eq!(render("* a\n* b"), "<ul>\n<li>a</li>\n<li>b</li>\n</ul>\n");
+        assert_eq!(render("* a\n\n* b"), "<ul>\n<li>\n<p>a</p>\n</li>\n<li>\n<p>b</p>\n</li>\n</ul>\n");
+        assert_eq!(render("3. a\n   * b"), "<ol start=\"3\">\n<li>a\n<ul>\n<li>b</li>\n</ul>\n</li>\n</ol>\n");
+        assert_eq!(render("* [x] done"),
+                   "<ul>\n<li><input type=\"checkbox\" disabled=\"\" checked=\"\" /> done</li>\n</ul>\n");
+    }
+
+    #[test]
+    fn references_and_footnotes() {
+        assert_eq!(render("[a][b] ![c][b]\n\n[b]: /url"),
+                   "<p><a href=\"/url\">a</a> <img src=\"/url\" alt=\"c\" /></p>\n");
+
+        let html = render("A[^Note] B[^note]\n\n[^note]: Note");
+        assert!(html.contains("<sup><a href=\"#user-content-fn-note\" id=\"user-content-fnref-note\""));
+        assert!(html.contains("id=\"user-content-fnref-note-2\""));
+        assert!(html.contains("<li id=\"user-content-fn-note\">\n<p>Note <a href=\"#user-content-fnref-note\""));
+        assert!(html.contains("<a href=\"#user-content-fnref-note-2\""));
+    }
+
+    #[test]
+    fn tables() {
+        assert_eq!(render("| a | b |\n| :- | -: |\n| c | d |"),
+                   "<table>\n<thead>\n<tr>\n<th align=\"left\">a</th>\n<th align=\"right\">b</th>\n</tr>\n</thead>\n\
+                    <tbody>\n<tr>\n<td align=\"left\">c</td>\n<td align=\"right\">d</td>\n</tr>\n</tbody>\n</table>\n");
+    }
+
+    /// Returns `html` without the ids j3sg gives headings, which markdown-rs
+    /// does not
+    fn without_heading_ids(html: &str) -> String {
+        let mut stripped = String::with_capacity(html.len());
+        let mut rest = html;
+        while let Some(start) = rest.find("<h") {
+            let tag = &rest[start..];
+            let is_heading = tag.as_bytes().get(2).is_some_and(|b| (b'1'..=b'6').contains(b))
+                && tag[3..].starts_with(" id=\"")
+                && !tag[3..].starts_with(" id=\"footnote-label\"");
+            let end = if is_heading { 3 } else { 2 };
+            stripped.push_str(&rest[..start + end]);
+            rest = &rest[start + end..];
+            if is_heading {
+                rest = &rest[rest[5..].find('"').unwrap() + 6..];
+            }
+        }
+        stripped.push_str(rest);
+        stripped
+    }
+
+    #[test]
+    fn same_html_as_markdown_rs() {
+        let corpus = [
+            // CommonMark
+            "# One\n## Two\n### Three *em*\n\nSetext\n======\n\nSetext\n------",
+            "A paragraph\nover two lines  \nwith a hard break\\\nand another.",
+            "*em* _em_ **strong** __strong__ ***both*** `code` `` a`b `` `  ` `a\nb`",
+            "\\*not em\\* &amp; &copy; &#35; < > \" '",
+            "[inline](/url \"title\") [empty]() <https://example.com> <a@b.com>",
+            "[full][ref] [collapsed][] [shortcut] [missing]\n\n[ref]: /ref 'Title'\n[collapsed]: /c\n[shortcut]: </s s>",
+            "![alt *text*](/img.png \"t\") ![ref][img]\n\n[img]: /i.png",
+            "[bad](javascript:alert(1)) [ok](mailto:a@b.com) [space](/a b) [utf](/ü)",
+            "> quote\n> > nested\n\n> lazy\ncontinuation",
+            "* a\n* b\n\n- c\n\n- d\n\n1. one\n2. two\n\n7) seven\n8) eight",
+            "* a\n\n  more a\n* b\n  * nested\n\n    loose nested\n* c",
+            "1. a\n   > quote\n   ```\n   code\n   ```\n2. b",
+            "*\n* \n\n-\n  foo",
+            "    indented code\n      more\n\n```\nfenced < &\n```\n\n~~~ python extra\ndef f(): pass\n~~~\n\n```\n```",
+            "***\n---\n___\n\nText\n\n* * *",
+            "<div class=\"note\">\n*raw*\n</div>\n\nInline <span>html</span> and <!-- comment -->",
+            // GFM
+            "~~struck~~ ~one~ www.example.com https://example.com/a?b=c. a@b.com",
+            "| a | b | c |\n| :- | :-: | -: |\n| 1 | `|` | **3** |\n| only one |",
+            "| a |\n| - |",
+            "* [ ] todo\n* [x] done\n\n1. [X] ordered",
+            "Text[^a] and[^b] and again[^a].\n\n[^a]: First\n[^b]: Second\n\n    with code\n\n[^unused]: Unused",
+            "Missing[^nope] footnote, [^Odd Label] and [^a:b]\n\n[^odd label]: Odd\n[^a:b]: Colon",
+            "Note[^q]\n\n[^q]: > quoted",
+        ];
+        let options = MarkdownOptions { highlight: false, math: true, ..Default::default() };
+        let markdown_options = markdown::Options {
+            parse: options.parse_options(),
+            compile: markdown::CompileOptions {
+                allow_dangerous_html: true,
+                ..Default::default()
+            },
+        };
+        for text in corpus.into_iter().chain(["$x$ and $$y$$\n\n$$\nz\n$$"]) {
+            let expected = markdown::to_html_with_options(text, &markdown_options).unwrap();
+            // markdown-rs does not end its HTML with a line ending
+            let html = without_heading_ids(&render_with(text, &options));
+            assert_eq!(html.trim_end(), expected.trim_end(), "rendering {:?}", text);
+        }
+    }
+
+    #[test]
+    fn sanitize_urls() {
+        assert_eq!(sanitize_url("https://example.com/a b?c=ü#d"), "https://example.com/a%20b?c=%C3%BC#d");
+        assert_eq!(sanitize_url("/a%20b"), "/a%20b");
+        assert_eq!(sanitize_url("javascript:alert(1)"), "");
+        assert_eq!(sanitize_url("../a:b"), "../a:b");
+    }
+}
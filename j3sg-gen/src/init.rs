@@ -1,5 +1,15 @@
 //! Init defaults
 
+pub const CONFIG: &[u8] = 
+b"base_url = \"http://127.0.0.1:5000\"
+title = \"My Site\"
+author = \"Alice\"
+description = \"A brand new site\"
+
+[extra]
+"
+;
+
 pub const ROOT_INDEX: &[u8] = 
 b"---
 title: My Site
@@ -7,6 +7,7 @@
 //!
 
 mod page;
+mod date;
 mod section;
 mod uri;
 mod filesystem;
@@ -14,34 +15,51 @@ mod parse;
 mod render;
 mod sitemap;
 mod init;
-
-use std::fs::{self, OpenOptions};
+mod config;
+mod error;
+mod watch;
+mod cache;
+mod feed;
+mod crawlers;
+mod paginate;
+mod taxonomy;
+mod html;
+mod highlight;
+mod toc;
+mod links;
+mod schema;
+mod slug;
+
+use std::fs;
 use std::path::Path;
+use chrono::Utc;
+use rayon::prelude::*;
 use tera::{Tera, Context};
 
 use sitemap::SiteMap;
 use page::PageMap;
 use section::SectionMap;
+use cache::Cache;
+use links::Links;
+use render::Renderer;
+use taxonomy::TaxonomyMap;
+
+pub use config::{Config, CONFIG_FILE};
+pub use error::{Error, Result};
+pub use watch::watch;
 
 
 /// Generates the site
 ///
 /// TODO: Break this function TF up
-pub fn generate<B, T, S>(
-    src_dir: B, 
-    out_dir: T, 
-    template_dir: S
-) -> Result<(), String> 
-where
-    B: AsRef<Path>,
-    T: AsRef<Path>,
-    S: AsRef<Path>
-{
-    let (src_dir, out_dir, template_dir) = (src_dir.as_ref(), out_dir.as_ref(), template_dir.as_ref());
+pub fn generate(config: &Config) -> Result<()> {
+    let src_dir = config.src_dir.as_path();
+    let out_dir = config.out_dir.as_path();
+    let template_dir = config.template_dir.as_path();
 
     if !out_dir.is_dir() {
-        fs::create_dir(out_dir)
-            .map_err(|e| e.to_string())?;
+        fs::create_dir_all(out_dir)
+            .map_err(|e| Error::io(out_dir, e))?;
     }
 
     // 01 -- Parse source directory structure
@@ -51,88 +69,169 @@ where
 
 
     // 02 -- Read in source files
-    let sectionmap = SectionMap::new(&sitemap)?;
-    let pagemap = PageMap::new(&sitemap)?;
-    // TODO: Check that root index is there and has a nonempty template field!
-    let root_index = src_dir.join("index.md");
-    if !root_index.is_file() {
-        return Err("No root index file found".to_string());
+    let cache = Cache::load(&config.cache_dir, config.markdown.clone(), Links::new(&sitemap));
+    let mut sectionmap = SectionMap::new(&sitemap, &cache)?;
+    let mut pagemap = PageMap::new(&sitemap, &cache)?;
+    if !config.drafts {
+        let now = Utc::now().fixed_offset();
+        let unpublished = pagemap.remove_unpublished(now) + sectionmap.remove_unpublished(now);
+        if unpublished > 0 {
+            println!("Skipped {} draft or scheduled pages", unpublished);
+        }
     }
+    config.schema.validate(&pagemap, &sectionmap)?;
+    sectionmap.retain_pages(&pagemap);
+    sectionmap.sort_pages(&pagemap);
+    let taxonomies = TaxonomyMap::new(&config.taxonomies, &pagemap)?;
+    // generated pages must not overwrite the output of a source file
+    let mut generated = paginate::uris(&sectionmap, &pagemap);
+    generated.extend(taxonomies.uris(template_dir));
+    sitemap.check_generated(generated)?;
     // TODO: Fill in empty fields
     
 
     // 03 -- Render
     // insert global objects 
     let mut context = Context::new();
+    context.insert("config", config);
     context.insert("SECTION_MAP", &sectionmap);
     context.insert("PAGE_MAP", &pagemap);
+    context.insert("TAXONOMIES", &taxonomies);
 
     // TODO: move this tera init to a function
     let glob = template_dir.join("**/*.html").to_string_lossy().to_string();
     let mut tera = Tera::new(&glob)
-        .map_err(|e| e.to_string())?;
+        .map_err(|source| Error::Template { path: template_dir.to_owned(), source })?;
     tera.autoescape_on(vec![]);
 
-    // finally render every page
-    for (uri, page) in pagemap.0.iter() {
-        fs::create_dir_all(uri.out_dir(out_dir))
-            .map_err(|_| "unable to create folder".to_string())?;
+    // finally render every page whose inputs have changed, in parallel
+    let renderer = Renderer::new(&tera, config, &cache, &pagemap, &sectionmap, &taxonomies);
+
+    // paginated section indexes are rendered once per page of their listing
+    let sectioniter = sectionmap.0.values()
+        .filter_map(|section| section.index.as_ref().map(|index| (section, index)))
+        .flat_map(|(section, index)| {
+            let paginators = paginate::paginate(section, &pagemap);
+            if paginators.is_empty() {
+                vec![(section, index, None)]
+            } else {
+                paginators.into_iter()
+                    .map(|paginator| (section, index, Some(paginator)))
+                    .collect()
+            }
+        });
+    let pageiter = pagemap.0.values()
+        .map(|page| (&sectionmap.0[&page.section], page, None));
+    // jobs are grouped by section, so that each thread mostly renders pages
+    // of the same section one after another
+    let mut jobs: Vec<_> = pageiter.chain(sectioniter).collect();
+    jobs.sort_by(|a, b| a.0.uri.cmp(&b.0.uri));
+
+    // Each thread gets its own copy of the context, rather than each page,
+    // and only replaces its section when it moves on to another one. The
+    // section inserted is the one the renderer fingerprints pages with.
+    let mut results: Vec<Result<bool>> = jobs.par_iter()
+        .map_init(
+            || (context.clone(), None),
+            |(context, current), (section, page, paginator)| {
+                if *current != Some(&section.uri) {
+                    context.insert("section", renderer.section(&section.uri));
+                    *current = Some(&section.uri);
+                }
+                context.insert("page", page);
+                context.insert("breadcrumbs", &sectionmap.breadcrumbs(&page.uri));
+                match paginator {
+                    Some(paginator) => context.insert("paginator", paginator),
+                    None => { context.remove("paginator"); }
+                }
+                renderer.render(context, page, section, paginator.as_ref())
+            })
+        .collect();
+
+    // each taxonomy has a page listing its terms, and a page per term
+    let taxonomy_jobs: Vec<_> = taxonomies.0.values()
+        .flat_map(|taxonomy| std::iter::once((taxonomy, None))
+            .chain(taxonomy.terms.iter().map(move |term| (taxonomy, Some(term)))))
+        .collect();
+    results.par_extend(taxonomy_jobs.par_iter()
+        .map_init(
+            || context.clone(),
+            |context, (taxonomy, term)| {
+                context.insert("taxonomy", taxonomy);
+                match term {
+                    Some(term) => context.insert("term", term),
+                    None => { context.remove("term"); }
+                }
+                renderer.render_taxonomy(context, taxonomy, *term)
+            }));
+
+    let rendered = error::collect(results)?
+        .into_iter()
+        .filter(|&rendered| rendered)
+        .count();
+    let total = jobs.len() + taxonomy_jobs.len();
+    println!("Rendered {} of {} pages, the rest are unchanged", rendered, total);
+
+
+    // 04 -- Copy static files
+    if config.static_dir.is_dir() {
+        let mirrored = filesystem::copy_dir(&config.static_dir, out_dir.join(&config.static_mount))?;
+        for file in &mirrored.files {
+            cache.record(file);
+        }
+        println!("Copied {} static files", mirrored.copied);
+    }
 
-        let section = sectionmap.0.get(&page.section)
-            .ok_or("Page with no section?".to_string())?;
-    
-        context.insert("page", page);
-        context.insert("section", section);
-
-        let out_path = uri.out_path(out_dir);
-        let outfile = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(&out_path)
-            .map_err(|e| format!("Error writing output file: {}", e))?;
-        let template = "base.html";
-        tera.render_to(template, &context, outfile)
-            .map_err(|e| e.to_string())?;
 
+    // 05 -- Write feeds
+    let feeds = feed::write_feeds(config, &sectionmap, &pagemap, &cache)?;
+    if feeds > 0 {
+        println!("Wrote feeds for {} sections", feeds);
     }
 
-    let sectioniter = sectionmap.0.iter()
-        .filter(|(_, section)| section.index.is_some());
-    for (uri, section) in sectioniter {
-        fs::create_dir_all(uri.out_dir(out_dir))
-            .map_err(|_| "unable to create folder".to_string())?;
-
-        context.insert("page", &section.index);
-        context.insert("section", section);
-
-        let out_path = uri.out_path(out_dir);
-        let outfile = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(&out_path)
-            .map_err(|e| format!("Error writing output file: {}", e))?;
-        let template = "base.html";
-        tera.render_to(template, &context, outfile)
-            .map_err(|e| e.to_string())?;
+
+    // 06 -- Write sitemap.xml and robots.txt
+    let listed = crawlers::write_crawler_files(config, &sectionmap, &pagemap, &cache)?;
+    println!("Listed {} pages in the sitemap", listed);
+
+
+    // 07 -- Remove files written by the last build but not this one, e.g
+    // of pages which have since been deleted or made drafts
+    let removed = cache.prune(out_dir)?;
+    if removed > 0 {
+        println!("Removed {} files left over from the last build", removed);
     }
+    cache.save()?;
 
     Ok(())
 }
 
-pub fn init(src_dir: &Path, out_dir: &Path, template_dir: &Path, static_dir: &Path) -> std::io::Result<()> {
+/// Initializes a new site in the directories given by `config`
+///
+/// # Arguments
+///
+/// * `config` - The site configuration
+/// * `config_path` - Where to write a default configuration file, if none exists
+pub fn init<P>(config: &Config, config_path: P) -> Result<()>
+where
+    P: AsRef<Path>
+{
+    let config_path = config_path.as_ref();
 
-    fs::create_dir_all(src_dir)?;
-    fs::create_dir_all(out_dir)?;
-    fs::create_dir_all(template_dir)?;
-    fs::create_dir_all(static_dir)?;
+    for dir in [&config.src_dir, &config.out_dir, &config.template_dir, &config.static_dir] {
+        fs::create_dir_all(dir)
+            .map_err(|e| Error::io(dir, e))?;
+    }
 
-    filesystem::cat(src_dir.join("index.md"), init::ROOT_INDEX)?;
-    filesystem::cat(template_dir.join("base.html"), init::BASE_TEMPLATE)?;
+    if !config_path.exists() {
+        filesystem::cat(config_path, init::CONFIG)?;
+    }
+    filesystem::cat(config.src_dir.join("index.md"), init::ROOT_INDEX)?;
+    filesystem::cat(config.template_dir.join("base.html"), init::BASE_TEMPLATE)?;
     Ok(())
 }
 
 
 #[cfg(test)]
 pub mod test {
-    use super::*;
 }
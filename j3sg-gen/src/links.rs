@@ -0,0 +1,123 @@
+//! Links between source files
+//!
+//! A relative link to another markdown file, such as
+//! `[setup](../getting-started/installation.md#linux)`, is rewritten to the
+//! URI that file is generated at, `/getting-started/installation#linux`.
+//! Such a link to a file which does not exist is an error.
+
+use crate::sitemap::SiteMap;
+use crate::uri::Uri;
+use crate::error::{Error, Result};
+
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+/// The URI every source file is generated at, by path
+#[derive(Debug, Default)]
+pub struct Links(HashMap<PathBuf, Uri>);
+impl Links {
+    pub fn new(sitemap: &SiteMap) -> Self {
+        let sections = sitemap.sections.iter()
+            .map(|(uri, dir)| (dir.join("index.md"), uri));
+        let pages = sitemap.pages.iter()
+            .map(|(uri, src)| (src.clone(), uri));
+        let map = sections.chain(pages)
+            .map(|(src, uri)| (normalize(&src), uri.clone()))
+            .collect();
+        Self(map)
+    }
+
+    /// Returns the URI a link in the source file `src` should point to,
+    /// or `None` if it is not a relative link to a markdown file and should
+    /// be left as is
+    ///
+    /// # Arguments
+    ///
+    /// * `src` - The path of the source file containing the link
+    /// * `url` - The URL of the link
+    /// * `line` - The line the link is on, for error reporting
+    pub fn resolve(&self, src: &Path, url: &str, line: Option<usize>) -> Result<Option<String>> {
+        let (path, suffix) = match url.find(['?', '#']) {
+            Some(i) => url.split_at(i),
+            None => (url, ""),
+        };
+        let is_absolute = path.starts_with('/')
+            || path.find([':', '/']).is_some_and(|i| path[i..].starts_with(':'));
+        if is_absolute || !path.ends_with(".md") {
+            return Ok(None);
+        }
+
+        let dir = src.parent().unwrap_or(Path::new(""));
+        match self.0.get(&normalize(&dir.join(path))) {
+            Some(uri) => Ok(Some(format!("{}{}", uri, suffix))),
+            None => Err(Error::BrokenLink {
+                path: src.to_owned(),
+                line,
+                link: url.to_string(),
+            }),
+        }
+    }
+}
+
+/// Removes `.` and `..` segments from `path` without touching the
+/// filesystem, keeping any `..` which go above the start of the path
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                Some(Component::RootDir) => {}
+                _ => normalized.push(".."),
+            },
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    fn links() -> Links {
+        let uri = |s: &str| s.split('/')
+            .filter(|s| !s.is_empty())
+            .fold(Uri::new(), |uri, segment| uri.join(segment).unwrap());
+        Links(HashMap::from([
+            (PathBuf::from("src/index.md"), uri("")),
+            (PathBuf::from("src/docs/index.md"), uri("docs")),
+            (PathBuf::from("src/docs/setup.md"), uri("docs/setup")),
+            (PathBuf::from("src/docs/more/usage.md"), uri("docs/usage")),
+        ]))
+    }
+
+    #[test]
+    fn resolve_relative_links() {
+        let links = links();
+        let src = Path::new("./src/docs/more/usage.md");
+        let resolve = |url| links.resolve(src, url, None).unwrap();
+        assert_eq!(resolve("../setup.md").as_deref(), Some("/docs/setup"));
+        assert_eq!(resolve("./../index.md#top").as_deref(), Some("/docs#top"));
+        assert_eq!(resolve("../../index.md").as_deref(), Some("/"));
+        assert_eq!(resolve("usage.md?x=1").as_deref(), Some("/docs/usage?x=1"));
+    }
+
+    #[test]
+    fn other_links_are_left_alone() {
+        let links = links();
+        let src = Path::new("src/index.md");
+        for url in ["https://example.com/a.md", "/docs/setup.md", "#setup.md", "setup", "mailto:a.md"] {
+            assert_eq!(links.resolve(src, url, None).unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn broken_link() {
+        let error = links().resolve(Path::new("src/index.md"), "docs/nope.md", Some(3)).unwrap_err();
+        assert_eq!(error.to_string(), "src/index.md:3: broken link \"docs/nope.md\", no such source file");
+    }
+}
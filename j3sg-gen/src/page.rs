@@ -1,11 +1,16 @@
 use crate::uri::Uri;
+use crate::date::{self, Date};
 use crate::filesystem::file_stem;
+use crate::cache::Cache;
 use crate::parse::Parse;
 use crate::sitemap::SiteMap;
+use crate::error::{self, Error, Result};
+use crate::toc::TocEntry;
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::fs;
 use std::collections::HashMap;
+use rayon::prelude::*;
 use serde::Serialize;
 use serde_yaml::Value;
 
@@ -13,57 +18,172 @@ use serde_yaml::Value;
 pub struct Page {
     pub uri: Uri,
     pub section: Uri,
+    pub src: PathBuf,
     pub title: String,
     pub author: Option<String>,
     pub description: Option<String>,
     pub template: Option<String>,
+    pub date: Option<Date>,
+    pub updated: Option<Date>,
+    /// Whether this page is listed in `sitemap.xml`
+    pub sitemap: bool,
+    /// Whether this page is unfinished, and only built with `--drafts`
+    pub draft: bool,
     pub content: String,
+    /// The page's headings, nested by level
+    pub toc: Vec<TocEntry>,
 
     #[serde(flatten)]
     pub extra: HashMap<String, Value>, 
 }
 impl Page {
-    pub fn new<P>(uri: Uri, section_uri: Uri, src: P) -> Result<Self, String> 
+    pub fn new<P>(uri: Uri, section_uri: Uri, src: P, cache: &Cache) -> Result<Self> 
     where
         P: AsRef<Path>
     {
         let src = src.as_ref();
         let text = fs::read_to_string(src)
-            .map_err(|e| e.to_string())?;
-        let parse = Parse::from_str(&text)?;
+            .map_err(|e| Error::io(src, e))?;
+        let parse = cache.parse(src, &text)?;
+        Self::from_parse(uri, section_uri, src, parse)
+    }
+
+    /// Creates a page from its already parsed source file
+    ///
+    /// # Arguments
+    ///
+    /// * `uri` - The URI of the page
+    /// * `section_uri` - The URI of the section the page belongs to
+    /// * `src` - The path of the source file
+    /// * `parse` - The result of parsing the source file
+    pub fn from_parse(uri: Uri, section_uri: Uri, src: &Path, parse: Parse) -> Result<Self> {
+        let parse_date = |s: &str| date::parse(s)
+            .ok_or_else(|| Error::FrontMatter {
+                path: src.to_owned(),
+                line: None,
+                column: None,
+                message: format!("invalid date \"{}\"", s),
+            });
+        let date = match parse.date {
+            Some(ref s) => Some(parse_date(s)?),
+            None => date::from_file_stem(&file_stem(src)?),
+        };
+        let updated = parse.updated.as_deref()
+            .map(parse_date)
+            .transpose()?;
+
         let page = Page {
             uri,
             section: section_uri,
+            src: src.to_owned(),
             title: parse.title.unwrap_or(file_stem(src)?),
             author: parse.author,
             description: parse.description, 
             template: parse.template,
+            date,
+            updated,
+            sitemap: parse.sitemap.unwrap_or(true),
+            draft: parse.draft.unwrap_or(false),
             content: parse.content,
+            toc: parse.toc,
 
             extra: parse.extra,
         };
         Ok(page)
     }
+
+    /// Whether this page should be built without `--drafts`, i.e it is not
+    /// a draft, and it is not dated in the future
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The time of the build
+    pub fn is_published(&self, now: Date) -> bool {
+        !self.draft && self.date.is_none_or(|date| date <= now)
+    }
 }
 
 #[derive(Debug, Serialize)]
 #[serde(transparent)]
 pub struct PageMap(pub HashMap<Uri, Page>);
 impl PageMap {
-    pub fn new(sitemap: &SiteMap) -> Result<Self, String> {
-        let mut map = HashMap::new();
-        let subpages = &sitemap.subpages;
-        for (section_uri, page_uris) in subpages {
-            for page_uri in page_uris {
-                let src = sitemap.pages.get(page_uri)
-                    .ok_or("Page not found in sitemap")?;
-                let page = Page::new(
-                    page_uri.clone(),
-                    section_uri.clone(),
-                    src)?;
-                map.insert(page_uri.clone(), page);
-            }
-        }
+    /// Reads in every page of `sitemap` in parallel
+    ///
+    /// If any page cannot be read, every error is returned rather than
+    /// just the first.
+    pub fn new(sitemap: &SiteMap, cache: &Cache) -> Result<Self> {
+        let results: Vec<Result<Page>> = sitemap.subpages.par_iter()
+            .flat_map_iter(|(section_uri, page_uris)| page_uris.iter()
+                .map(move |page_uri| (section_uri, page_uri)))
+            .map(|(section_uri, page_uri)| Page::new(
+                page_uri.clone(),
+                section_uri.clone(),
+                &sitemap.pages[page_uri],
+                cache))
+            .collect();
+
+        let map = error::collect(results)?
+            .into_iter()
+            .map(|page| (page.uri.clone(), page))
+            .collect();
         Ok(Self(map))
     }
+
+    /// Removes drafts and pages dated after `now`, returning how many
+    /// were removed
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The time of the build
+    pub fn remove_unpublished(&mut self, now: Date) -> usize {
+        let before = self.0.len();
+        self.0.retain(|_, page| page.is_published(now));
+        before - self.0.len()
+    }
+}
+
+#[cfg(test)]
+impl Page {
+    /// Returns a page at `uri`, in the section above it, with every other
+    /// field empty
+    pub fn test(uri: Uri) -> Self {
+        Self {
+            section: uri.parent().unwrap_or_else(Uri::new),
+            uri,
+            src: PathBuf::new(),
+            title: String::new(),
+            author: None,
+            description: None,
+            template: None,
+            date: None,
+            updated: None,
+            sitemap: true,
+            draft: false,
+            content: String::new(),
+            toc: Vec::new(),
+            extra: HashMap::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    fn page(date: Option<&str>, draft: bool) -> Page {
+        Page {
+            date: date.and_then(date::parse),
+            draft,
+            ..Page::test(Uri::new())
+        }
+    }
+
+    #[test]
+    fn drafts_and_scheduled_pages_are_unpublished() {
+        let now = date::parse("2023-09-01").unwrap();
+        assert!(page(None, false).is_published(now));
+        assert!(page(Some("2023-09-01"), false).is_published(now));
+        assert!(!page(Some("2023-09-02"), false).is_published(now));
+        assert!(!page(None, true).is_published(now));
+    }
 }
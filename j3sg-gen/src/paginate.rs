@@ -0,0 +1,191 @@
+//! Splitting section listings over several pages
+//!
+//! A section whose `index.md` sets `paginate_by` has its index rendered once
+//! per `paginate_by` pages: the first at the section's own URI, and the rest
+//! at `<section>/page/2`, `<section>/page/3`, and so on. Each is rendered with
+//! a `paginator` object holding the pages it lists and links to its
+//! neighbours.
+
+use crate::uri::Uri;
+use crate::page::{Page, PageMap};
+use crate::section::{Section, SectionMap};
+
+use std::path::PathBuf;
+use serde::Serialize;
+
+/// One page of a section's listing
+#[derive(Debug, Serialize)]
+pub struct Paginator<'a> {
+    /// The URI this page of the listing is rendered to
+    pub uri: Uri,
+    /// The pages listed on this page, newest first
+    pub pages: Vec<&'a Page>,
+    /// The number of this page of the listing, starting at 1
+    pub number: usize,
+    /// How many pages the listing is split over
+    pub total: usize,
+    /// How many pages are listed across the whole listing
+    pub total_pages: usize,
+    pub previous: Option<Uri>,
+    pub next: Option<Uri>,
+    pub first: Uri,
+    pub last: Uri,
+}
+
+/// Splits the pages of `section` into paginators, or returns nothing if
+/// the section is not paginated
+///
+/// A paginated section with no pages still gets a single, empty paginator.
+///
+/// # Arguments
+///
+/// * `section` - The section to paginate, with its pages sorted
+/// * `pagemap` - The pages to look the section's pages up in
+pub fn paginate<'a>(section: &Section, pagemap: &'a PageMap) -> Vec<Paginator<'a>> {
+    let Some(per_page) = section.paginate_by else { return Vec::new() };
+
+    let pages: Vec<&Page> = section.pages.iter()
+        .filter_map(|uri| pagemap.0.get(uri))
+        .collect();
+    let total = pages.len().div_ceil(per_page).max(1);
+    let uri = |number| pager_uri(&section.uri, number);
+
+    (1..=total)
+        .map(|number| Paginator {
+            uri: uri(number),
+            pages: pages.iter()
+                .skip((number - 1) * per_page)
+                .take(per_page)
+                .copied()
+                .collect(),
+            number,
+            total,
+            total_pages: pages.len(),
+            previous: (number > 1).then(|| uri(number - 1)),
+            next: (number < total).then(|| uri(number + 1)),
+            first: uri(1),
+            last: uri(total),
+        })
+        .collect()
+}
+
+/// Returns the URIs taken by the listings of the paginated sections of
+/// `sectionmap`, along with the index of each section, e.g to check them
+/// against the URIs of source files
+///
+/// Besides the URI of every page of a listing after the first, this is
+/// `<section>/page`, so that a `page` subsection or `page.md` is never
+/// mixed in with the listing, however few pages it has.
+///
+/// # Arguments
+///
+/// * `sectionmap` - The sections of the site, with their pages sorted
+/// * `pagemap` - The pages to look the sections' pages up in
+pub fn uris(sectionmap: &SectionMap, pagemap: &PageMap) -> Vec<(Uri, PathBuf)> {
+    sectionmap.0.values()
+        .filter(|section| section.paginate_by.is_some())
+        .filter_map(|section| section.index.as_ref().map(|index| (section, &index.src)))
+        .flat_map(|(section, src)| {
+            let prefix = section.uri.join("page")
+                .expect("page is a valid uri segment");
+            std::iter::once(prefix)
+                .chain(paginate(section, pagemap).into_iter().skip(1).map(|paginator| paginator.uri))
+                .map(|uri| (uri, src.clone()))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Returns the URI of page `number` of the listing of the section at `uri`
+///
+/// # Arguments
+///
+/// * `uri` - The URI of the section
+/// * `number` - The number of the page of the listing, starting at 1
+pub fn pager_uri(uri: &Uri, number: usize) -> Uri {
+    if number <= 1 {
+        return uri.clone();
+    }
+    uri.join("page")
+        .and_then(|uri| uri.join(number.to_string()))
+        .expect("page numbers are valid uri segments")
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn blog(pages: usize, paginate_by: Option<usize>) -> (Section, PageMap) {
+        let uri = Uri::new().join("blog").unwrap();
+        let pagemap: HashMap<Uri, Page> = (0..pages)
+            .map(|i| {
+                let page = Page::test(uri.join(format!("post-{}", i)).unwrap());
+                (page.uri.clone(), page)
+            })
+            .collect();
+
+        let mut pages: Vec<Uri> = pagemap.keys().cloned().collect();
+        pages.sort();
+        let section = Section {
+            paginate_by,
+            pages,
+            ..Section::test(uri)
+        };
+        (section, PageMap(pagemap))
+    }
+
+    #[test]
+    fn unpaginated_section() {
+        let (section, pagemap) = blog(3, None);
+        assert!(paginate(&section, &pagemap).is_empty());
+    }
+
+    #[test]
+    fn split_into_pages() {
+        let (section, pagemap) = blog(5, Some(2));
+        let paginators = paginate(&section, &pagemap);
+        assert_eq!(paginators.len(), 3);
+
+        let first = &paginators[0];
+        assert_eq!(first.uri.to_string(), "/blog");
+        assert_eq!(first.pages.len(), 2);
+        assert_eq!(first.pages[0].uri.to_string(), "/blog/post-0");
+        assert_eq!(first.previous, None);
+        assert_eq!(first.next.as_ref().map(Uri::to_string).as_deref(), Some("/blog/page/2"));
+
+        let last = &paginators[2];
+        assert_eq!(last.uri.to_string(), "/blog/page/3");
+        assert_eq!(last.pages.len(), 1);
+        assert_eq!(last.pages[0].uri.to_string(), "/blog/post-4");
+        assert_eq!(last.next, None);
+        assert_eq!(last.first, first.uri);
+        assert_eq!(last.total_pages, 5);
+    }
+
+    #[test]
+    fn empty_section_has_one_page() {
+        let (section, pagemap) = blog(0, Some(10));
+        let paginators = paginate(&section, &pagemap);
+        assert_eq!(paginators.len(), 1);
+        assert!(paginators[0].pages.is_empty());
+    }
+
+    #[test]
+    fn listing_uris() {
+        let (section, pagemap) = blog(5, Some(2));
+        let section = Section {
+            index: Some(Page { src: PathBuf::from("src/blog/index.md"), ..Page::test(section.uri.clone()) }),
+            ..section
+        };
+        let sectionmap = SectionMap(HashMap::from([(section.uri.clone(), section)]));
+        let uris: Vec<String> = uris(&sectionmap, &pagemap).into_iter()
+            .map(|(uri, src)| format!("{} {}", uri, src.display()))
+            .collect();
+        assert_eq!(uris, [
+            "/blog/page src/blog/index.md",
+            "/blog/page/2 src/blog/index.md",
+            "/blog/page/3 src/blog/index.md",
+        ]);
+    }
+}
@@ -1,93 +1,435 @@
+use crate::error::{Error, Result};
+use crate::highlight::DEFAULT_THEME;
+use crate::html;
+use crate::links::Links;
+use crate::toc::TocEntry;
+
+use std::path::Path;
 use std::collections::HashMap;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_yaml::Value;
 use markdown::{
-    mdast::{Node, Root, Yaml},
-    to_html_with_options, to_mdast,
-    Constructs, Options, ParseOptions
+    mdast::{Node, Root, Toml, Yaml},
+    unist::Position,
+    to_mdast,
+    Constructs, ParseOptions
 };
 
-#[derive(Debug)]
+/// Which markdown extensions are enabled, on top of CommonMark
+///
+/// These are set site-wide under `[markdown]` in `j3sg.toml`, and can be
+/// overridden per page under `markdown` in its front matter.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MarkdownOptions {
+    /// GitHub flavored markdown: tables, strikethrough, task lists,
+    /// autolinks and footnotes
+    pub gfm: bool,
+    /// Raw HTML, which is passed through to the output as is
+    pub html: bool,
+    /// `$inline$` and `$$` block math
+    pub math: bool,
+    /// Syntax highlighting of fenced code blocks
+    pub highlight: bool,
+    /// The syntect theme code is highlighted with
+    pub highlight_theme: String,
+    /// A `#` link to each heading, inside the heading
+    pub heading_anchors: bool,
+}
+
+impl Default for MarkdownOptions {
+    fn default() -> Self {
+        Self {
+            gfm: true,
+            html: true,
+            math: false,
+            highlight: true,
+            highlight_theme: DEFAULT_THEME.to_string(),
+            heading_anchors: false,
+        }
+    }
+}
+
+impl MarkdownOptions {
+    /// Returns these options with any fields set in `overrides` replaced
+    fn with(self, overrides: MarkdownOverrides) -> Self {
+        Self {
+            gfm: overrides.gfm.unwrap_or(self.gfm),
+            html: overrides.html.unwrap_or(self.html),
+            math: overrides.math.unwrap_or(self.math),
+            highlight: overrides.highlight.unwrap_or(self.highlight),
+            highlight_theme: self.highlight_theme,
+            heading_anchors: overrides.heading_anchors.unwrap_or(self.heading_anchors),
+        }
+    }
+
+    /// Returns the markdown-rs options these options correspond to
+    pub fn parse_options(&self) -> ParseOptions {
+        let mut constructs = if self.gfm {
+            Constructs::gfm()
+        } else {
+            Constructs::default()
+        };
+        constructs.frontmatter = true;
+        constructs.html_flow = self.html;
+        constructs.html_text = self.html;
+        constructs.math_flow = self.math;
+        constructs.math_text = self.math;
+
+        ParseOptions {
+            constructs,
+            ..Default::default()
+        }
+    }
+}
+
+/// The markdown options a page's front matter overrides
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MarkdownOverrides {
+    gfm: Option<bool>,
+    html: Option<bool>,
+    math: Option<bool>,
+    highlight: Option<bool>,
+    heading_anchors: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Parse {
     pub title: Option<String>,
     pub author: Option<String>,
     pub description: Option<String>,
     pub template: Option<String>,
+    pub page_template: Option<String>,
+    pub paginate_by: Option<usize>,
+    pub date: Option<String>,
+    pub updated: Option<String>,
+    pub feed: Option<bool>,
+    pub sitemap: Option<bool>,
+    pub draft: Option<bool>,
     pub content: String,
+    pub toc: Vec<TocEntry>,
+    /// Relative links to other source files, and the URIs they were
+    /// rewritten to
+    pub links: HashMap<String, String>,
 
     pub extra: HashMap<String, Value>, 
 }
 impl Parse {
-    pub fn from_str(text: &str) -> Result<Self, String> {
-        let opts = Options {
-            parse: ParseOptions {
-                constructs: Constructs {
-                    frontmatter: true,
-                    html_flow: true,
-                    html_text: true,
-                    ..Default::default()
-                },
-                ..Default::default()
-            },
-            ..Default::default()
+    /// Parses the front matter and markdown content of a source file
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The contents of the source file
+    /// * `src` - The path of the source file, for error reporting
+    /// * `options` - The site's markdown options, before the page's overrides
+    /// * `links` - The source files links may point to
+    pub fn from_str(text: &str, src: &Path, options: &MarkdownOptions, links: &Links) -> Result<Self> {
+        // JSON front matter is not markdown, so it is split off first, and
+        // YAML or TOML front matter is read from the syntax tree
+        let (json, body) = FrontMatter::from_json(text, src)?;
+        let mut root = to_mdast(body, &options.parse_options())
+            .map_err(|message| Error::markdown(src, message))?;
+        let mut frontmatter = match json {
+            Some(frontmatter) => frontmatter,
+            None => FrontMatter::from_tree(&root, src)?,
         };
 
-        let frontmatter = FrontMatter::from_str(text)
-            .unwrap_or(Default::default());
-        let content = to_html_with_options(text, &opts)?;
+        // The page's overrides may change the syntax it is written in, and
+        // only then is it parsed a second time
+        let parse_options = options.parse_options();
+        let options = options.clone().with(frontmatter.markdown.take().unwrap_or_default());
+        if options.parse_options().constructs != parse_options.constructs {
+            root = to_mdast(body, &options.parse_options())
+                .map_err(|message| Error::markdown(src, message))?;
+        }
+        // Lines of the body are counted from after any JSON front matter
+        let skipped_lines = text[..text.len() - body.len()].matches('\n').count();
+
+        let mut rewritten = HashMap::new();
+        let mut errors = Vec::new();
+        let (content, toc) = html::to_html(&root, &options, &mut |url, line| {
+            match links.resolve(src, url, line.map(|line| line + skipped_lines)) {
+                Ok(uri) => {
+                    if let Some(uri) = &uri {
+                        rewritten.insert(url.to_string(), uri.clone());
+                    }
+                    uri
+                }
+                Err(e) => {
+                    errors.push(e);
+                    None
+                }
+            }
+        });
+        if !errors.is_empty() {
+            return Err(Error::from_vec(errors));
+        }
 
         Ok(Self {
             title: frontmatter.title,
             author: frontmatter.author,
             description: frontmatter.description,
             template: frontmatter.template,
+            page_template: frontmatter.page_template,
+            paginate_by: frontmatter.paginate_by,
+            date: frontmatter.date,
+            updated: frontmatter.updated,
+            feed: frontmatter.feed,
+            sitemap: frontmatter.sitemap,
+            draft: frontmatter.draft,
             extra: frontmatter.extra,
             content,
+            toc,
+            links: rewritten,
         })
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize)]
 struct FrontMatter {
     title: Option<String>,
     author: Option<String>,
     description: Option<String>,
     template: Option<String>,
+    page_template: Option<String>,
+    paginate_by: Option<usize>,
+    date: Option<String>,
+    updated: Option<String>,
+    feed: Option<bool>,
+    sitemap: Option<bool>,
+    draft: Option<bool>,
+    markdown: Option<MarkdownOverrides>,
 
     #[serde(flatten)]
     extra: HashMap<String, Value>, 
 }
-impl Default for FrontMatter {
-    fn default() -> Self {
-        Self {
-            title: None,
-            author: None,
-            description: None,
-            template: None,
-            extra: HashMap::new(),
+impl FrontMatter {
+    /// Reads the YAML front matter between `---` lines, or the TOML front
+    /// matter between `+++` lines, at the start of a parsed document
+    ///
+    /// # Arguments
+    ///
+    /// * `root` - The syntax tree of the source file
+    /// * `src` - The path of the source file, for error reporting
+    fn from_tree(root: &Node, src: &Path) -> Result<Self> {
+        let Node::Root(Root { children, .. }) = root
+            else { return Ok(Self::default()) };
+
+        match children.first() {
+            Some(Node::Yaml(Yaml { value, position })) => Self::from_yaml(value, position, src),
+            Some(Node::Toml(Toml { value, position })) => Self::from_toml(value, position, src),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    fn from_yaml(value: &str, position: &Option<Position>, src: &Path) -> Result<Self> {
+        if value.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        serde_yaml::from_str(value).map_err(|e| {
+            let location = e.location();
+            Error::FrontMatter {
+                path: src.to_owned(),
+                line: location.as_ref().map(|location| location.line() + first_line(position)),
+                column: location.as_ref().map(|location| location.column()),
+                message: without_location(e.to_string()),
+            }
+        })
+    }
+
+    fn from_toml(value: &str, position: &Option<Position>, src: &Path) -> Result<Self> {
+        let table: toml::Table = toml::from_str(value).map_err(|e| {
+            let location = e.span().map(|span| {
+                let before = &value[..span.start];
+                let line = before.matches('\n').count() + 1;
+                let column = before.chars().rev().take_while(|&ch| ch != '\n').count() + 1;
+                (line + first_line(position), column)
+            });
+            Error::FrontMatter {
+                path: src.to_owned(),
+                line: location.map(|(line, _)| line),
+                column: location.map(|(_, column)| column),
+                message: e.message().to_string(),
+            }
+        })?;
+
+        // TOML dates have no YAML equivalent, so values are converted by
+        // hand, with dates becoming strings
+        serde_yaml::from_value(toml_to_yaml(toml::Value::Table(table)))
+            .map_err(|e| Error::FrontMatter {
+                path: src.to_owned(),
+                line: None,
+                column: None,
+                message: e.to_string(),
+            })
+    }
+
+    /// Reads the JSON object at the very start of `text`, if there is one,
+    /// returning it along with the markdown which follows it
+    ///
+    /// Only a `{` alone on the first line opens JSON front matter, so that
+    /// markdown which happens to start with a brace is left alone.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The contents of the source file
+    /// * `src` - The path of the source file, for error reporting
+    fn from_json<'a>(text: &'a str, src: &Path) -> Result<(Option<Self>, &'a str)> {
+        if text.lines().next().map(str::trim_end) != Some("{") {
+            return Ok((None, text));
+        }
+
+        let mut objects = serde_json::Deserializer::from_str(text).into_iter::<Self>();
+        match objects.next() {
+            Some(Ok(frontmatter)) => Ok((Some(frontmatter), &text[objects.byte_offset()..])),
+            // Nothing past the opening brace could be read, so the file is
+            // plain markdown after all
+            Some(Err(e)) if e.line() == 1 => Ok((None, text)),
+            Some(Err(e)) => Err(Error::FrontMatter {
+                path: src.to_owned(),
+                line: Some(e.line()),
+                column: Some(e.column()),
+                message: without_location(e.to_string()),
+            }),
+            None => Ok((None, text)),
         }
     }
 }
-impl FrontMatter {
-    fn from_str(text: &str) -> Option<Self> {
-        let parseopts = ParseOptions {
-            constructs: Constructs {
-                frontmatter: true,
-                ..Default::default()
-            },
-            ..Default::default()
-        };
 
-        let ast = to_mdast(text, &parseopts).ok()?;
+/// Returns the line of the file before the first line of front matter,
+/// i.e the line of the opening `---` or `+++`
+fn first_line(position: &Option<Position>) -> usize {
+    position.as_ref().map_or(1, |position| position.start.line)
+}
+
+/// Removes the ` at line L column C` serde_yaml and serde_json append to
+/// their messages, since the location is reported separately
+fn without_location(message: String) -> String {
+    match message.rsplit_once(" at line ") {
+        Some((message, _)) => message.to_string(),
+        None => message,
+    }
+}
+
+/// Converts a TOML value to the equivalent YAML value
+fn toml_to_yaml(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(i) => Value::Number(i.into()),
+        toml::Value::Float(f) => Value::Number(f.into()),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(datetime) => Value::String(datetime.to_string()),
+        toml::Value::Array(array) => Value::Sequence(array.into_iter().map(toml_to_yaml).collect()),
+        toml::Value::Table(table) => Value::Mapping(table.into_iter()
+            .map(|(key, value)| (Value::String(key), toml_to_yaml(value)))
+            .collect()),
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    fn parse(text: &str, options: MarkdownOptions) -> Parse {
+        Parse::from_str(text, Path::new("test.md"), &options, &Links::default()).unwrap()
+    }
+
+    #[test]
+    fn gfm_by_default() {
+        let parse = parse("~~no~~\n\n| a |\n| - |\n| b |\n\n* [x] done", MarkdownOptions::default());
+        assert!(parse.content.contains("<del>no</del>"));
+        assert!(parse.content.contains("<table>"));
+        assert!(parse.content.contains("type=\"checkbox\""));
+    }
+
+    #[test]
+    fn raw_html_can_be_disabled() {
+        let text = "<div class=\"note\">Hi</div>";
+        assert!(parse(text, MarkdownOptions::default()).content.contains("<div class=\"note\">"));
+
+        let options = MarkdownOptions { html: false, ..Default::default() };
+        assert!(parse(text, options).content.contains("&lt;div"));
+    }
+
+    #[test]
+    fn front_matter_overrides_site_options() {
+        let text = "---\nmarkdown:\n  math: true\n---\n$x$";
+        let parse = parse(text, MarkdownOptions::default());
+        assert!(parse.content.contains("language-math"));
+        assert!(!parse.extra.contains_key("markdown"));
+
+        let text = "---\nmarkdown:\n  maths: true\n---\n$x$";
+        let error = Parse::from_str(text, Path::new("test.md"), &MarkdownOptions::default(), &Links::default())
+            .unwrap_err();
+        assert!(matches!(error, Error::FrontMatter { line: Some(3), .. }));
+    }
+
+    #[test]
+    fn malformed_front_matter_is_an_error() {
+        let text = "---\ntitle: A\n- b\n---\n# Hi";
+        let error = Parse::from_str(text, Path::new("test.md"), &MarkdownOptions::default(), &Links::default())
+            .unwrap_err();
+        assert!(matches!(error, Error::FrontMatter { line: Some(3), column: Some(1), .. }));
+
+        let text = "---\ntitle: A\ntemplate: [b]\n---\n";
+        let error = Parse::from_str(text, Path::new("test.md"), &MarkdownOptions::default(), &Links::default())
+            .unwrap_err();
+        assert_eq!(error.to_string(), "test.md:3:11: invalid front matter: template: invalid type: sequence, expected a string");
+    }
+
+    #[test]
+    fn toml_front_matter() {
+        let text = "+++\ntitle = \"A\"\ndate = 2023-09-01\ntags = [\"b\"]\n[markdown]\nmath = true\n+++\n$x$";
+        let parse = parse(text, MarkdownOptions::default());
+        assert_eq!(parse.title.as_deref(), Some("A"));
+        assert_eq!(parse.date.as_deref(), Some("2023-09-01"));
+        assert_eq!(parse.extra["tags"], Value::Sequence(vec![Value::from("b")]));
+        assert!(parse.content.contains("language-math"));
+        assert!(!parse.content.contains("title"));
+
+        let error = Parse::from_str("+++\ntitle = \"A\"\ntitle = 1\n+++\n", Path::new("test.md"),
+                                    &MarkdownOptions::default(), &Links::default()).unwrap_err();
+        assert!(matches!(error, Error::FrontMatter { line: Some(3), column: Some(1), .. }));
+    }
+
+    #[test]
+    fn json_front_matter() {
+        let text = "{\n  \"title\": \"A\",\n  \"weight\": 2\n}\n# Hi";
+        let parse = parse(text, MarkdownOptions::default());
+        assert_eq!(parse.title.as_deref(), Some("A"));
+        assert_eq!(parse.extra["weight"], Value::from(2));
+        assert_eq!(parse.content, "<h1 id=\"hi\">Hi</h1>\n");
+
+        let error = Parse::from_str("{\n  \"title\": 1\n}", Path::new("test.md"),
+                                    &MarkdownOptions::default(), &Links::default()).unwrap_err();
+        assert!(matches!(error, Error::FrontMatter { line: Some(2), .. }));
+    }
+
+    #[test]
+    fn braces_in_markdown_are_not_json() {
+        let braces = parse("{{ name }} says hi", MarkdownOptions::default());
+        assert_eq!(braces.title, None);
+        assert_eq!(braces.content, "<p>{{ name }} says hi</p>\n");
+
+        let brace = parse("{", MarkdownOptions::default());
+        assert_eq!(brace.content, "<p>{</p>\n");
+    }
+
+    #[test]
+    fn empty_front_matter() {
+        assert_eq!(parse("---\n---\n# Hi", MarkdownOptions::default()).title, None);
+    }
 
-        let Node::Root(Root { children, .. }) = ast 
-            else { return None };
-        
-        let Some(Node::Yaml(Yaml {
-            value, ..
-        })) = children.get(0) 
-            else { return None };
+    #[test]
+    fn headings_and_toc() {
+        let text = "# Intro\n\n## Setup\n\n## Setup\n\n# Usage";
+        let parse = parse(text, MarkdownOptions { heading_anchors: true, ..Default::default() });
+        assert!(parse.content.contains("<h2 id=\"setup-1\">Setup <a class=\"anchor\" href=\"#setup-1\""));
 
-        serde_yaml::from_str(value).ok()
+        let ids: Vec<&str> = parse.toc.iter().map(|entry| entry.id.as_str()).collect();
+        assert_eq!(ids, ["intro", "usage"]);
+        assert_eq!(parse.toc[0].children.len(), 2);
+        assert_eq!(parse.toc[0].children[1].text, "Setup");
     }
 }
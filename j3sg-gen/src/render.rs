@@ -0,0 +1,435 @@
+//! Rendering pages to their output files with Tera
+//!
+//! Pages are only rendered if something they are rendered from has
+//! changed since the last build, see `Renderer::render`.
+
+use crate::cache::{self, Cache};
+use crate::config::Config;
+use crate::page::{Page, PageMap};
+use crate::section::{Breadcrumb, ResolvedSection, Section, SectionMap};
+use crate::paginate::Paginator;
+use crate::taxonomy::{Taxonomy, TaxonomyMap, Term};
+use crate::uri::Uri;
+use crate::error::{Error, Result};
+
+use std::fs::{self, OpenOptions};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use log::debug;
+use serde::Serialize;
+use tera::{Tera, Context};
+use tera::ast::Node;
+
+/// The template used when neither a page nor its section names one
+pub const DEFAULT_TEMPLATE: &str = "base.html";
+
+/// The global objects holding the whole site, see `Renderer::render`
+pub const GLOBALS: [&str; 3] = ["PAGE_MAP", "SECTION_MAP", "TAXONOMIES"];
+
+/// Returns the name of the template `page` should be rendered with
+///
+/// In order of precedence, this is
+/// * The page's own `template` front matter field
+/// * The `page_template` front matter field of its section's index,
+///   unless `page` is that index
+/// * `default`
+///
+/// # Arguments
+///
+/// * `page` - The page being rendered
+/// * `section` - The section `page` belongs to
+/// * `default` - The site-wide default template
+pub fn template_for<'a>(page: &'a Page, section: &'a Section, default: &'a str) -> &'a str {
+    let section_default = if page.uri == section.uri {
+        None
+    } else {
+        section.page_template.as_deref()
+    };
+
+    page.template.as_deref()
+        .or(section_default)
+        .unwrap_or(default)
+}
+
+/// Renders `template` into the output file of `uri` under `out_dir`
+///
+/// `uri` is normally the URI of a page, but differs for the later pages
+/// of a paginated section's listing, and for taxonomy pages.
+///
+/// # Arguments
+///
+/// * `tera` - The Tera instance holding every loaded template
+/// * `context` - The context to render with
+/// * `src` - The path errors are reported against, e.g the page's source file
+/// * `uri` - The URI being rendered
+/// * `template` - The name of the template to render with
+/// * `out_dir` - The path of the output directory
+pub fn render_page(
+    tera: &Tera,
+    context: &Context,
+    src: &Path,
+    uri: &Uri,
+    template: &str,
+    out_dir: &Path
+) -> Result<()> {
+    let dir = uri.out_dir(out_dir);
+    fs::create_dir_all(&dir)
+        .map_err(|e| Error::io(&dir, e))?;
+
+    let out_path = uri.out_path(out_dir);
+    let outfile = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&out_path)
+        .map_err(|e| Error::io(&out_path, e))?;
+    tera.render_to(template, context, outfile)
+        .map_err(|source| Error::Template { path: src.to_owned(), source })
+}
+
+/// Renders pages, skipping those whose inputs have not changed since the
+/// last build
+pub struct Renderer<'a> {
+    tera: &'a Tera,
+    config: &'a Config,
+    cache: &'a Cache,
+    pagemap: &'a PageMap,
+    sectionmap: &'a SectionMap,
+    taxonomies: &'a TaxonomyMap<'a>,
+    /// Hashes of each template and its dependencies, and whether any of
+    /// them use the global objects, by template name
+    template_hashes: Mutex<HashMap<String, (String, bool)>>,
+    /// Every section as templates see it, see `ResolvedSection`, along with
+    /// a hash of it, by section URI
+    sections: HashMap<Uri, (tera::Value, String)>,
+    /// A hash of the global objects, computed on first use
+    site_hash: OnceLock<String>,
+}
+
+/// Everything a page's output depends on, besides its template
+#[derive(Serialize)]
+struct Inputs<'a> {
+    config: &'a Config,
+    page: &'a Page,
+    section: &'a str,
+    paginator: Option<&'a Paginator<'a>>,
+    breadcrumbs: Vec<Breadcrumb<'a>>,
+}
+
+/// Everything a taxonomy page's output depends on, besides its template
+#[derive(Serialize)]
+struct TaxonomyInputs<'a> {
+    config: &'a Config,
+    taxonomy: &'a Taxonomy<'a>,
+    term: Option<&'a Term<'a>>,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(
+        tera: &'a Tera,
+        config: &'a Config,
+        cache: &'a Cache,
+        pagemap: &'a PageMap,
+        sectionmap: &'a SectionMap,
+        taxonomies: &'a TaxonomyMap<'a>
+    ) -> Self {
+        // Each section is resolved and hashed just once, rather than once
+        // per page in it
+        let sections = sectionmap.0.values()
+            .map(|section| {
+                let value = tera::to_value(ResolvedSection::new(section, pagemap, sectionmap))
+                    .expect("sections are always serializable");
+                let hash = cache::hash(&serde_json::to_vec(&value)
+                    .expect("sections are always serializable"));
+                (section.uri.clone(), (value, hash))
+            })
+            .collect();
+
+        Self {
+            tera,
+            config,
+            cache,
+            pagemap,
+            sectionmap,
+            taxonomies,
+            template_hashes: Mutex::new(HashMap::new()),
+            sections,
+            site_hash: OnceLock::new(),
+        }
+    }
+
+    /// Renders `page` with `context` into its output file, unless it was
+    /// rendered from identical inputs by the last build. Returns whether
+    /// the page was rendered.
+    ///
+    /// A page's inputs are its template and every template it depends on,
+    /// the page itself, its section along with the pages and subsections
+    /// listed in it, its paginator if any, its breadcrumbs, and the site
+    /// configuration. If any of its
+    /// templates use `PAGE_MAP`, `SECTION_MAP` or `TAXONOMIES`, the whole
+    /// site is an input too.
+    ///
+    /// # Arguments
+    ///
+    /// * `context` - The context to render with
+    /// * `page` - The page being rendered
+    /// * `section` - The section `page` belongs to
+    /// * `paginator` - The page of the section's listing being rendered,
+    ///   if `page` is the index of a paginated section
+    pub fn render(
+        &self,
+        context: &Context,
+        page: &Page,
+        section: &Section,
+        paginator: Option<&Paginator>
+    ) -> Result<bool> {
+        let template = template_for(page, section, &self.config.default_template);
+        let inputs = Inputs {
+            config: self.config,
+            page,
+            section: &self.sections[&section.uri].1,
+            paginator,
+            breadcrumbs: self.sectionmap.breadcrumbs(&page.uri),
+        };
+        let uri = paginator.map_or(&page.uri, |paginator| &paginator.uri);
+        self.render_inputs(context, template, uri, &page.src, &inputs)
+    }
+
+    /// Renders the list of terms of `taxonomy`, or if `term` is given, the
+    /// list of pages with that term, unless it was rendered from identical
+    /// inputs by the last build. Returns whether anything was rendered.
+    ///
+    /// The inputs of a taxonomy page are its template, the whole taxonomy,
+    /// and the site configuration, as well as the whole site if its
+    /// templates use the global objects.
+    ///
+    /// # Arguments
+    ///
+    /// * `context` - The context to render with
+    /// * `taxonomy` - The taxonomy being rendered
+    /// * `term` - The term being rendered, if any
+    pub fn render_taxonomy(
+        &self,
+        context: &Context,
+        taxonomy: &Taxonomy,
+        term: Option<&Term>
+    ) -> Result<bool> {
+        let (template, uri) = match term {
+            Some(term) => (taxonomy.single_template(), &term.uri),
+            None => (taxonomy.list_template(), &taxonomy.uri),
+        };
+        let inputs = TaxonomyInputs {
+            config: self.config,
+            taxonomy,
+            term,
+        };
+        self.render_inputs(context, &template, uri, &self.config.template_dir, &inputs)
+    }
+
+    /// Renders `template` into the output file of `uri`, unless it was last
+    /// rendered from the same template and `inputs`
+    fn render_inputs<T>(
+        &self,
+        context: &Context,
+        template: &str,
+        uri: &Uri,
+        src: &Path,
+        inputs: &T
+    ) -> Result<bool>
+    where
+        T: Serialize
+    {
+        if !self.tera.get_template_names().any(|name| name == template) {
+            return Err(Error::MissingTemplate {
+                path: src.to_owned(),
+                template: template.to_string(),
+            });
+        }
+
+        // Going through a Value sorts the keys of every map
+        let inputs = serde_json::to_value(inputs)
+            .and_then(|value| serde_json::to_vec(&value))
+            .expect("render inputs are always serializable");
+        let (template_hash, globals) = self.template_hash(template)?;
+        let site_hash = if globals { self.site_hash() } else { "" };
+        let fingerprint = cache::hash(format!(
+            "{}{}{}",
+            template_hash,
+            cache::hash(&inputs),
+            site_hash,
+        ).as_bytes());
+
+        let out_path = uri.out_path(&self.config.out_dir);
+        if self.cache.is_fresh(&out_path, &fingerprint) {
+            return Ok(false);
+        }
+        render_page(self.tera, context, src, uri, template, &self.config.out_dir)
+            .inspect_err(|_| self.cache.invalidate(&out_path))?;
+        debug!("Rendered {}", uri.colored());
+        Ok(true)
+    }
+
+    /// Returns the hash of `template` and its dependencies, see `template_hash`
+    fn template_hash(&self, template: &str) -> Result<(String, bool)> {
+        if let Some(hash) = self.template_hashes.lock().unwrap().get(template) {
+            return Ok(hash.clone());
+        }
+        let hash = template_hash(self.tera, template)?;
+        self.template_hashes.lock().unwrap()
+            .insert(template.to_string(), hash.clone());
+        Ok(hash)
+    }
+
+    /// Returns `section` as templates see it, see `ResolvedSection`
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - The URI of the section
+    pub fn section(&self, section: &Uri) -> &tera::Value {
+        &self.sections[section].0
+    }
+
+    /// Returns a hash of `PAGE_MAP`, `SECTION_MAP` and `TAXONOMIES`
+    fn site_hash(&self) -> &str {
+        self.site_hash.get_or_init(|| {
+            // Going through a Value sorts the keys of every map
+            let site = serde_json::to_value((self.pagemap, self.sectionmap, self.taxonomies))
+                .and_then(|value| serde_json::to_vec(&value))
+                .expect("the site is always serializable");
+            cache::hash(&site)
+        })
+    }
+}
+
+/// Returns a hash of the source of `template`, along with the sources of every
+/// template it extends, includes, or imports macros from, recursively, and
+/// whether any of those sources use one of the `GLOBALS`
+///
+/// # Arguments
+///
+/// * `tera` - The Tera instance holding every loaded template
+/// * `template` - The name of the template
+pub fn template_hash(tera: &Tera, template: &str) -> Result<(String, bool)> {
+    let mut seen = HashSet::new();
+    let mut stack = vec![template.to_string()];
+    let mut sources = Vec::new();
+    let mut globals = false;
+
+    while let Some(name) = stack.pop() {
+        if !seen.insert(name.clone()) {
+            continue;
+        }
+        // Missing templates are only possible for `ignore missing` includes
+        let Ok(template) = tera.get_template(&name) else { continue };
+
+        match template.path {
+            Some(ref path) => {
+                let source = fs::read(path)
+                    .map_err(|e| Error::io(path, e))?;
+                let text = String::from_utf8_lossy(&source);
+                globals |= GLOBALS.iter().any(|global| text.contains(global));
+                sources.push((name, cache::hash(&source)));
+            }
+            None => sources.push((name, String::new())),
+        }
+
+        stack.extend(template.parents.iter().cloned());
+        stack.extend(template.imported_macro_files.iter().map(|(file, _)| file.clone()));
+        includes(&template.ast, &mut stack);
+        for definition in template.macros.values() {
+            includes(&definition.body, &mut stack);
+        }
+    }
+
+    sources.sort();
+    let sources: Vec<String> = sources.into_iter()
+        .map(|(name, hash)| format!("{}:{}", name, hash))
+        .collect();
+    Ok((cache::hash(sources.join("\n").as_bytes()), globals))
+}
+
+/// Pushes the names of every template included in `nodes` onto `names`
+fn includes(nodes: &[Node], names: &mut Vec<String>) {
+    for node in nodes {
+        match node {
+            Node::Include(_, templates, _) => names.extend(templates.iter().cloned()),
+            Node::MacroDefinition(_, definition, _) => includes(&definition.body, names),
+            Node::FilterSection(_, section, _) => includes(&section.body, names),
+            Node::Block(_, block, _) => includes(&block.body, names),
+            Node::Forloop(_, forloop, _) => {
+                includes(&forloop.body, names);
+                if let Some(ref body) = forloop.empty_body {
+                    includes(body, names);
+                }
+            }
+            Node::If(condition, _) => {
+                for (_, _, body) in &condition.conditions {
+                    includes(body, names);
+                }
+                if let Some((_, ref body)) = condition.otherwise {
+                    includes(body, names);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    fn page(uri: Uri, section: Uri, template: Option<&str>) -> Page {
+        Page {
+            section,
+            template: template.map(String::from),
+            ..Page::test(uri)
+        }
+    }
+
+    fn section(uri: Uri, page_template: Option<&str>) -> Section {
+        Section {
+            page_template: page_template.map(String::from),
+            ..Section::test(uri)
+        }
+    }
+
+    #[test]
+    fn page_template_takes_precedence() {
+        let root = section(Uri::new(), Some("post.html"));
+        let page = page(Uri::new().join("foo").unwrap(), Uri::new(), Some("own.html"));
+        assert_eq!(template_for(&page, &root, DEFAULT_TEMPLATE), "own.html");
+    }
+
+    #[test]
+    fn falls_back_to_section_page_template() {
+        let root = section(Uri::new(), Some("post.html"));
+        let page = page(Uri::new().join("foo").unwrap(), Uri::new(), None);
+        assert_eq!(template_for(&page, &root, DEFAULT_TEMPLATE), "post.html");
+    }
+
+    #[test]
+    fn template_hash_finds_globals() {
+        let dir = std::env::temp_dir().join("j3sg-test-template-globals");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("base.html"), "{% block body %}{% endblock %}").unwrap();
+        fs::write(dir.join("post.html"), "{% extends \"base.html\" %}{% block body %}{{ page.title }}{% endblock %}").unwrap();
+        fs::write(dir.join("list.html"), "{% extends \"base.html\" %}{% block body %}{{ PAGE_MAP | length }}{% endblock %}").unwrap();
+        let tera = Tera::new(&dir.join("*.html").to_string_lossy()).unwrap();
+        let (post, post_globals) = template_hash(&tera, "post.html").unwrap();
+        let (list, list_globals) = template_hash(&tera, "list.html").unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(!post_globals);
+        assert!(list_globals);
+        assert_ne!(post, list);
+    }
+
+    #[test]
+    fn index_ignores_section_page_template() {
+        let root = section(Uri::new(), Some("post.html"));
+        let index = page(Uri::new(), Uri::new(), None);
+        assert_eq!(template_for(&index, &root, DEFAULT_TEMPLATE), DEFAULT_TEMPLATE);
+    }
+}
@@ -0,0 +1,220 @@
+//! Checking pages against a site-defined schema
+//!
+//! `j3sg.toml` may describe the front matter fields pages are expected to
+//! have, each under `[schema.<field>]`, e.g
+//!
+//! ```toml
+//! [schema.description]
+//! required = true
+//!
+//! [schema.status]
+//! type = "string"
+//! values = ["draft", "review", "stable"]
+//! ```
+//!
+//! Fields are checked on the `page` object templates see, so `title` is
+//! always present, falling back to the file name, and `date` may come from
+//! the file name. Fields which are not in the schema are not checked.
+//!
+//! Section indexes are pages too, and are checked the same way.
+
+use crate::page::{Page, PageMap};
+use crate::section::SectionMap;
+use crate::date;
+use crate::error::{self, Error, Result};
+
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+
+/// The expected fields of every page, by name
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct Schema(pub BTreeMap<String, Field>);
+
+/// What is expected of a single field
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Field {
+    /// Whether every page must set the field
+    pub required: bool,
+    /// The type the field must have, if set
+    #[serde(rename = "type")]
+    pub kind: Option<Kind>,
+    /// The values the field may take, if restricted
+    pub values: Option<Vec<Value>>,
+}
+
+/// The type of a field
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    String,
+    Integer,
+    /// Any number, including integers
+    Float,
+    Boolean,
+    /// A string in any of the forms page dates may take
+    Date,
+    List,
+    Map,
+}
+
+impl Kind {
+    fn matches(self, value: &Value) -> bool {
+        match self {
+            Self::String => value.is_string(),
+            Self::Integer => value.is_i64() || value.is_u64(),
+            Self::Float => value.is_number(),
+            Self::Boolean => value.is_bool(),
+            Self::Date => value.as_str().and_then(date::parse).is_some(),
+            Self::List => value.is_sequence(),
+            Self::Map => value.is_mapping(),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::String => "a string",
+            Self::Integer => "an integer",
+            Self::Float => "a number",
+            Self::Boolean => "a boolean",
+            Self::Date => "a date",
+            Self::List => "a list",
+            Self::Map => "a map",
+        }
+    }
+}
+
+impl Field {
+    /// Checks the value of a field, which is `None` if it is not set,
+    /// returning what is wrong with it
+    fn check(&self, value: Option<&Value>) -> std::result::Result<(), String> {
+        let Some(value) = value.filter(|value| !value.is_null()) else {
+            return if self.required { Err("is required".to_string()) } else { Ok(()) };
+        };
+        if let Some(kind) = self.kind.filter(|kind| !kind.matches(value)) {
+            return Err(format!("should be {}", kind.name()));
+        }
+        match &self.values {
+            Some(values) if !values.contains(value) => {
+                let values: Vec<String> = values.iter().map(display).collect();
+                Err(format!("is {}, expected one of {}", display(value), values.join(", ")))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Schema {
+    /// Checks every page of `pagemap` and every section index of
+    /// `sectionmap` against this schema, returning every problem found
+    /// rather than just the first
+    ///
+    /// # Arguments
+    ///
+    /// * `pagemap` - The pages to check
+    /// * `sectionmap` - The sections whose indexes to check
+    pub fn validate(&self, pagemap: &PageMap, sectionmap: &SectionMap) -> Result<()> {
+        if self.0.is_empty() {
+            return Ok(());
+        }
+        let indexes = sectionmap.0.values()
+            .filter_map(|section| section.index.as_ref());
+        error::collect(pagemap.0.values().chain(indexes).map(|page| self.validate_page(page)))?;
+        Ok(())
+    }
+
+    fn validate_page(&self, page: &Page) -> Result<()> {
+        let value = serde_yaml::to_value(page)
+            .expect("pages are always serializable");
+        let errors: Vec<Error> = self.0.iter()
+            .filter_map(|(name, field)| field.check(value.get(name.as_str())).err()
+                .map(|problem| Error::FrontMatter {
+                    path: page.src.clone(),
+                    line: None,
+                    column: None,
+                    message: format!("field \"{}\" {}", name, problem),
+                }))
+            .collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::from_vec(errors))
+        }
+    }
+}
+
+/// Shows a value the way it would be written in front matter
+fn display(value: &Value) -> String {
+    serde_json::to_string(value).unwrap_or_else(|_| format!("{:?}", value))
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::uri::Uri;
+    use crate::section::Section;
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    fn schema() -> Schema {
+        toml::from_str(r#"
+            [description]
+            required = true
+
+            [status]
+            type = "string"
+            values = ["draft", "stable"]
+
+            [reviewed]
+            type = "date"
+        "#).unwrap()
+    }
+
+    fn page(description: Option<&str>, extra: &str) -> Page {
+        Page {
+            src: PathBuf::from("src/page.md"),
+            description: description.map(str::to_string),
+            extra: serde_yaml::from_str::<Option<_>>(extra).unwrap().unwrap_or_default(),
+            ..Page::test(Uri::new())
+        }
+    }
+
+    fn problems(page: &Page) -> Vec<String> {
+        match schema().validate_page(page) {
+            Ok(()) => Vec::new(),
+            Err(Error::Multiple(errors)) => errors.iter().map(Error::to_string).collect(),
+            Err(error) => vec![error.to_string()],
+        }
+    }
+
+    #[test]
+    fn valid_pages() {
+        assert!(problems(&page(Some("A page"), "")).is_empty());
+        assert!(problems(&page(Some("A page"), "status: stable\nreviewed: 2023-09-01")).is_empty());
+    }
+
+    #[test]
+    fn section_indexes_are_checked() {
+        let index = page(None, "");
+        let section = Section { index: Some(index), ..Section::test(Uri::new()) };
+        let sectionmap = SectionMap(HashMap::from([(Uri::new(), section)]));
+        let pagemap = PageMap(HashMap::new());
+
+        let error = schema().validate(&pagemap, &sectionmap).unwrap_err();
+        assert_eq!(error.to_string(), "src/page.md: invalid front matter: field \"description\" is required");
+    }
+
+    #[test]
+    fn invalid_pages() {
+        assert_eq!(problems(&page(None, "status: 1")), [
+            "src/page.md: invalid front matter: field \"description\" is required",
+            "src/page.md: invalid front matter: field \"status\" should be a string",
+        ]);
+        assert_eq!(problems(&page(Some("A page"), "status: done\nreviewed: soon")), [
+            "src/page.md: invalid front matter: field \"reviewed\" should be a date",
+            "src/page.md: invalid front matter: field \"status\" is \"done\", expected one of \"draft\", \"stable\"",
+        ]);
+    }
+}
@@ -1,13 +1,18 @@
 use crate::uri::Uri;
 use crate::sitemap::SiteMap;
 use crate::filesystem::file_name;
-use crate::parse::Parse;
-use crate::page::Page;
+use crate::cache::Cache;
+use crate::page::{Page, PageMap};
+use crate::date::Date;
+use crate::error::{self, Error, Result};
 
 use std::fs;
 use std::path::Path;
+use std::cmp::Reverse;
 use std::collections::HashMap;
-use serde::Serialize;
+use rayon::prelude::*;
+use serde::{Serialize, Serializer};
+use serde::ser::Error as _;
 use serde_yaml::Value;
 
 #[derive(Debug, Serialize)]
@@ -16,6 +21,12 @@ pub struct Section {
     pub uri: Uri,
     pub title: String,
     pub index: Option<Page>,
+    pub page_template: Option<String>,
+    /// How many pages each page of this section's listing shows, if it
+    /// is split into several
+    pub paginate_by: Option<usize>,
+    /// Whether RSS and Atom feeds are generated for this section
+    pub feed: bool,
     pub subsections: Vec<Uri>,
     pub pages: Vec<Uri>,
 
@@ -23,59 +34,263 @@ pub struct Section {
     pub extra: HashMap<String, Value>, 
 }
 impl Section {
-    pub fn new<P>(sitemap: &SiteMap, uri: Uri, src: P) -> Result<Self, String> 
+    pub fn new<P>(sitemap: &SiteMap, uri: Uri, src: P, cache: &Cache) -> Result<Self> 
     where
         P: AsRef<Path>
     {
         let src = src.as_ref();
-        let subsections = sitemap.subsections.get(&uri)
-            .ok_or("No subsection vec found?".to_string())?
-            .clone();
-        let pages = sitemap.subpages.get(&uri)
-            .ok_or("No subpages vec found?".to_string())?
-            .clone();
-
-        let text = fs::read_to_string(src.join("index.md"))
-            .map_err(|e| e.to_string())?;
-        let parse = Parse::from_str(&text)?;
-        let index = Page::new(
-            uri.clone(),
-            uri.clone(),
-            src.join("index.md"),
-        ).ok();
+        let subsections = sitemap.subsections[&uri].clone();
+        let pages = sitemap.subpages[&uri].clone();
+
+        let index_src = src.join("index.md");
+        let text = fs::read_to_string(&index_src)
+            .map_err(|e| Error::io(&index_src, e))?;
+        let parse = cache.parse(&index_src, &text)?;
         let parent = uri.parent();
 
         let section = Self {
             parent,
-            uri,
-            title: parse.title.unwrap_or(file_name(src)?),
-            index,
+            uri: uri.clone(),
+            title: parse.title.clone().unwrap_or(file_name(src)?),
+            page_template: parse.page_template.clone(),
+            paginate_by: parse.paginate_by.filter(|&n| n > 0),
+            feed: parse.feed.unwrap_or(false),
             subsections,
             pages, 
 
-            extra: parse.extra,
+            extra: parse.extra.clone(),
+            // The index page is made from the same parse, rather than
+            // reading and parsing index.md again
+            index: Some(Page::from_parse(uri.clone(), uri, &index_src, parse)?),
         };
         Ok(section)
     }
 }
 
+/// A section as templates see it, with its pages and subsections in place
+/// of their URIs
+///
+/// Only this section is resolved: the `pages` and `subsections` of its
+/// subsections are still URIs, which can be looked up in `PAGE_MAP` and
+/// `SECTION_MAP`.
+#[derive(Debug)]
+pub struct ResolvedSection<'a> {
+    pub section: &'a Section,
+    pub pages: Vec<&'a Page>,
+    pub subsections: Vec<&'a Section>,
+}
+impl<'a> ResolvedSection<'a> {
+    /// Looks up the pages and subsections of `section`, skipping any which
+    /// are not being built
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - The section to resolve
+    /// * `pagemap` - The pages to look the section's pages up in
+    /// * `sectionmap` - The sections to look the section's subsections up in
+    pub fn new(section: &'a Section, pagemap: &'a PageMap, sectionmap: &'a SectionMap) -> Self {
+        Self {
+            section,
+            pages: section.pages.iter()
+                .filter_map(|uri| pagemap.0.get(uri))
+                .collect(),
+            subsections: section.subsections.iter()
+                .filter_map(|uri| sectionmap.0.get(uri))
+                .collect(),
+        }
+    }
+}
+impl Serialize for ResolvedSection<'_> {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer
+    {
+        // Serializes like the section itself, with two fields replaced
+        let mut value = serde_json::to_value(self.section).map_err(S::Error::custom)?;
+        value["pages"] = serde_json::to_value(&self.pages).map_err(S::Error::custom)?;
+        value["subsections"] = serde_json::to_value(&self.subsections).map_err(S::Error::custom)?;
+        value.serialize(serializer)
+    }
+}
+
+/// A link to a section containing the page being rendered
+#[derive(Debug, Serialize)]
+pub struct Breadcrumb<'a> {
+    pub uri: &'a Uri,
+    pub title: &'a str,
+}
+
 #[derive(Debug, Serialize)]
 #[serde(transparent)]
 pub struct SectionMap(pub HashMap<Uri, Section>);
 impl SectionMap {
-    pub fn new(sitemap: &SiteMap) -> Result<Self, String> {
-        let mut map = HashMap::new();
-        let sections = &sitemap.sections;
-        for (uri, src) in sections {
-            let section = Section::new(&sitemap, uri.clone(), src)?;
-            map.insert(uri.clone(), section);
-        }
+    /// Reads in every section of `sitemap` in parallel
+    ///
+    /// If any section cannot be read, every error is returned rather than
+    /// just the first.
+    pub fn new(sitemap: &SiteMap, cache: &Cache) -> Result<Self> {
+        let results: Vec<Result<Section>> = sitemap.sections.par_iter()
+            .map(|(uri, src)| Section::new(sitemap, uri.clone(), src, cache))
+            .collect();
+
+        let map = error::collect(results)?
+            .into_iter()
+            .map(|section| (section.uri.clone(), section))
+            .collect();
         Ok(Self(map))
     }
+
+    /// Removes the index of every section whose index is a draft or dated
+    /// after `now`, so that it is not rendered or listed anywhere, returning
+    /// how many were removed
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The time of the build
+    pub fn remove_unpublished(&mut self, now: Date) -> usize {
+        let mut removed = 0;
+        for section in self.0.values_mut() {
+            if section.index.as_ref().is_some_and(|index| !index.is_published(now)) {
+                section.index = None;
+                removed += 1;
+            }
+        }
+        removed
+    }
+
+    /// Removes pages which are not in `pagemap` from every section, e.g
+    /// because they are drafts
+    ///
+    /// # Arguments
+    ///
+    /// * `pagemap` - The pages which are being built
+    pub fn retain_pages(&mut self, pagemap: &PageMap) {
+        for section in self.0.values_mut() {
+            section.pages.retain(|uri| pagemap.0.contains_key(uri));
+        }
+    }
+
+    /// Returns a breadcrumb for every section above the page or section at
+    /// `uri`, from the root section down
+    ///
+    /// # Arguments
+    ///
+    /// * `uri` - The URI of the page or section
+    pub fn breadcrumbs(&self, uri: &Uri) -> Vec<Breadcrumb<'_>> {
+        uri.ancestors().iter()
+            .filter_map(|ancestor| self.0.get(ancestor))
+            .map(|section| Breadcrumb { uri: &section.uri, title: &section.title })
+            .collect()
+    }
+
+    /// Orders the pages of every section from newest to oldest,
+    /// with undated pages last
+    ///
+    /// # Arguments
+    ///
+    /// * `pagemap` - The pages to look up dates in
+    pub fn sort_pages(&mut self, pagemap: &PageMap) {
+        for section in self.0.values_mut() {
+            section.pages.sort_by_cached_key(|uri| {
+                let date = pagemap.0.get(uri).and_then(|page| page.date);
+                (Reverse(date), uri.clone())
+            });
+        }
+    }
 }
 
 
+#[cfg(test)]
+impl Section {
+    /// Returns a section at `uri`, with every other field empty
+    pub fn test(uri: Uri) -> Self {
+        Self {
+            parent: uri.parent(),
+            uri,
+            title: String::new(),
+            index: None,
+            page_template: None,
+            paginate_by: None,
+            feed: false,
+            subsections: Vec::new(),
+            pages: Vec::new(),
+            extra: HashMap::new(),
+        }
+    }
+}
+
 #[cfg(test)]
 pub mod test {
     use super::*;
+
+    fn section(uri: &str, title: &str) -> Section {
+        Section {
+            title: title.to_string(),
+            ..Section::test(Uri::new().join(uri).unwrap())
+        }
+    }
+
+    #[test]
+    fn invalid_index_is_an_error() {
+        let dir = std::env::temp_dir().join("j3sg-test-section-index");
+        fs::remove_dir_all(&dir).unwrap_or(());
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("src/index.md"), "---\ndate: notadate\n---\n").unwrap();
+
+        let sitemap = SiteMap::new(dir.join("src")).unwrap();
+        let cache = Cache::load(dir.join("cache"), Default::default(), Default::default());
+        let result = SectionMap::new(&sitemap, &cache);
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(result, Err(Error::FrontMatter { .. })));
+    }
+
+    #[test]
+    fn unpublished_indexes_are_removed() {
+        let now = crate::date::parse("2023-09-01").unwrap();
+        let index = |draft| Some(Page { draft, ..Page::test(Uri::new()) });
+        let mut sectionmap = SectionMap([
+            Section { index: index(false), ..section("", "Home") },
+            Section { index: index(true), ..section("docs", "Docs") },
+        ].into_iter().map(|section| (section.uri.clone(), section)).collect());
+
+        assert_eq!(sectionmap.remove_unpublished(now), 1);
+        assert!(sectionmap.0[&Uri::new()].index.is_some());
+        assert!(sectionmap.0[&Uri::new().join("docs").unwrap()].index.is_none());
+    }
+
+    #[test]
+    fn resolve_pages_and_subsections() {
+        let mut docs = section("docs", "Docs");
+        let setup = Page {
+            title: "Setup".to_string(),
+            ..Page::test(docs.uri.join("setup").unwrap())
+        };
+        let guides = section("docs/guides", "Guides");
+        docs.pages = vec![setup.uri.clone(), docs.uri.join("draft").unwrap()];
+        docs.subsections = vec![guides.uri.clone()];
+
+        let pagemap = PageMap(HashMap::from([(setup.uri.clone(), setup)]));
+        let sectionmap = SectionMap(HashMap::from([(guides.uri.clone(), guides)]));
+        let value = serde_json::to_value(ResolvedSection::new(&docs, &pagemap, &sectionmap)).unwrap();
+        assert_eq!(value["title"], "Docs");
+        assert_eq!(value["pages"].as_array().unwrap().len(), 1);
+        assert_eq!(value["pages"][0]["title"], "Setup");
+        assert_eq!(value["subsections"][0]["title"], "Guides");
+    }
+
+    #[test]
+    fn breadcrumbs_from_root_down() {
+        let sectionmap = SectionMap([section("", "Home"), section("docs", "Docs"), section("docs/guides", "Guides")]
+            .into_iter()
+            .map(|section| (section.uri.clone(), section))
+            .collect());
+        let titles = |uri: &str| sectionmap.breadcrumbs(&Uri::new().join(uri).unwrap())
+            .iter()
+            .map(|breadcrumb| format!("{} {}", breadcrumb.uri, breadcrumb.title))
+            .collect::<Vec<_>>();
+
+        assert_eq!(titles("docs/guides/setup"), ["/ Home", "/docs Docs", "/docs/guides Guides"]);
+        assert_eq!(titles("docs/guides"), ["/ Home", "/docs Docs"]);
+        assert!(titles("").is_empty());
+    }
 }
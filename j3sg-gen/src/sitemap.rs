@@ -1,9 +1,15 @@
 //! A struct which holds the generated site's structure
 //!
 //! List of errors:
+//! * `Missing` if the source directory has no `index.md`
+//! * `Uri` if a source file's name is not a valid URI segment
+//! * `UriCollision` if several source files would be rendered to the same URI,
+//!   or a generated page, e.g of a taxonomy or of a paginated listing, would
+//!   overwrite a source file
 
 use crate::filesystem::{file_name, file_stem, files_with_extension, has_file, subdirs};
 use crate::uri::Uri;
+use crate::error::{Error, Result};
 use colored::*;
 
 use std::path::{Path, PathBuf};
@@ -16,28 +22,35 @@ pub struct SiteMap {
     pub subpages: HashMap<Uri, Vec<Uri>>, 
 }
 impl SiteMap {
-    pub fn new<P>(src_dir: P) -> Result<Self, String> 
+    pub fn new<P>(src_dir: P) -> Result<Self> 
     where 
         P: AsRef<Path>
     {
         let src_dir = src_dir.as_ref();
+        if !src_dir.join("index.md").is_file() {
+            return Err(Error::Missing { path: src_dir.join("index.md") });
+        }
         let mut sitemap = Self {
             sections: HashMap::new(),
             subsections: HashMap::new(),
             pages: HashMap::new(),
             subpages: HashMap::new(),
         };
+        let mut sources = vec![(Uri::new(), src_dir.join("index.md"))];
         sitemap.sections.insert(Uri::new(), src_dir.to_owned());
-        sitemap.build(&Uri::new())?;
+        sitemap.build(&Uri::new(), &mut sources)?;
+        check_collisions(sources)?;
 
         Ok(sitemap)
     }
 
     /// Recursively traverses the subdirectories of section[uri]  
     /// and inserts the corresponding subsections and subpages
-    fn build(&mut self, uri: &Uri) -> Result<(), String> {
-        let dir = self.sections.get(uri)
-            .ok_or("Uri does not exist in PageMap".to_string())?;
+    ///
+    /// Every Uri inserted is pushed to `sources` along with the
+    /// source file it was generated from.
+    fn build(&mut self, uri: &Uri, sources: &mut Vec<(Uri, PathBuf)>) -> Result<()> {
+        let dir = &self.sections[uri];
 
         // Traverses subdirectories to find immediate subsections and subpages
         let (sections, pages) = {
@@ -58,19 +71,23 @@ impl SiteMap {
         
         let mut subsections = Vec::new();
         for section in sections {
-            let section_uri = uri.join(file_name(&section)?)?;
+            let section_uri = uri.join(file_name(&section)?)
+                .ok_or_else(|| invalid_uri(&section))?;
             subsections.push(section_uri.clone());
+            sources.push((section_uri.clone(), section.join("index.md")));
             self.sections.insert(section_uri.clone(), section);
             
             // Recursive call
-            self.build(&section_uri)?;
+            self.build(&section_uri, sources)?;
         }
         self.subsections.insert(uri.clone(), subsections);
 
         let mut subpages = Vec::new();
         for page in pages {
-            let page_uri = uri.join(file_stem(&page)?)?;
+            let page_uri = uri.join(file_stem(&page)?)
+                .ok_or_else(|| invalid_uri(&page))?;
             subpages.push(page_uri.clone());
+            sources.push((page_uri.clone(), page.clone()));
             self.pages.insert(page_uri, page);
         }
         self.subpages.insert(uri.clone(), subpages);
@@ -112,8 +129,133 @@ impl SiteMap {
     pub fn print_tree(&self) {
         self.draw_uri_tree(&Uri::new(), 0);
     }
+
+    /// Checks that no page which is not rendered from a source file of its
+    /// own, e.g a taxonomy page, is rendered to the same Uri as a source file
+    ///
+    /// # Arguments
+    ///
+    /// * `generated` - The Uri of each generated page, with the path to
+    ///   report it against
+    pub fn check_generated(&self, generated: Vec<(Uri, PathBuf)>) -> Result<()> {
+        let sections = self.sections.iter()
+            .map(|(uri, dir)| (uri.clone(), dir.join("index.md")));
+        let pages = self.pages.iter()
+            .map(|(uri, src)| (uri.clone(), src.clone()));
+        check_collisions(sections.chain(pages).chain(generated).collect())
+    }
+}
+
+/// Checks that no two source files are rendered to the same Uri
+///
+/// Uris are compared case-insensitively, since they would collide
+/// in the output directory on case-insensitive filesystems.
+fn check_collisions(sources: Vec<(Uri, PathBuf)>) -> Result<()> {
+    let mut claims: HashMap<String, (Uri, Vec<PathBuf>)> = HashMap::new();
+    for (uri, src) in sources {
+        claims.entry(uri.to_string().to_lowercase())
+            .or_insert_with(|| (uri, Vec::new()))
+            .1.push(src);
+    }
+
+    let mut collisions: Vec<_> = claims.into_iter()
+        .filter(|(_, (_, paths))| paths.len() > 1)
+        .collect();
+    if collisions.is_empty() {
+        return Ok(());
+    }
+    collisions.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let errors = collisions.into_iter()
+        .map(|(_, (uri, mut paths))| {
+            paths.sort();
+            Error::UriCollision { uri: uri.to_string(), paths }
+        })
+        .collect();
+    Err(Error::from_vec(errors))
+}
+
+/// Returns the error for a source file whose name is not a valid URI segment
+fn invalid_uri(path: &Path) -> Error {
+    Error::Uri {
+        path: path.to_owned(),
+        message: "file names may only contain alphanumeric characters, \
+                  hyphens, underscores, and periods".to_string(),
+    }
 }
 
 #[cfg(test)]
 pub mod test {
+    use super::*;
+    use std::fs;
+
+    /// Creates a fresh source directory containing the given files
+    fn src_dir(name: &str, files: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("j3sg-test-sitemap-{}", name));
+        fs::remove_dir_all(&dir).unwrap_or(());
+        for file in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        dir
+    }
+
+    fn collisions(error: Error) -> Vec<Vec<PathBuf>> {
+        let errors = match error {
+            Error::Multiple(errors) => errors,
+            error => vec![error],
+        };
+        errors.into_iter()
+            .map(|error| match error {
+                Error::UriCollision { paths, .. } => paths,
+                error => panic!("unexpected error {}", error),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn no_collisions() {
+        let dir = src_dir("none", &["index.md", "foo.md", "bar/index.md", "bar/baz.md"]);
+        let sitemap = SiteMap::new(&dir).unwrap();
+        assert_eq!(sitemap.sections.len(), 2);
+        assert_eq!(sitemap.pages.len(), 2);
+    }
+
+    #[test]
+    fn page_collides_with_section() {
+        let dir = src_dir("section", &["index.md", "foo.md", "foo/index.md"]);
+        let error = SiteMap::new(&dir).err().unwrap();
+        assert_eq!(collisions(error), vec![vec![dir.join("foo/index.md"), dir.join("foo.md")]]);
+    }
+
+    #[test]
+    fn case_insensitive_collision() {
+        let dir = src_dir("case", &["index.md", "Foo.md", "foo.md"]);
+        let error = SiteMap::new(&dir).err().unwrap();
+        assert_eq!(collisions(error), vec![vec![dir.join("Foo.md"), dir.join("foo.md")]]);
+    }
+
+    #[test]
+    fn generated_page_collides_with_source() {
+        let dir = src_dir("generated", &["index.md", "tags/index.md", "tags/rust.md"]);
+        let sitemap = SiteMap::new(&dir).unwrap();
+        let template = PathBuf::from("templates/tags/list.html");
+        let tags = Uri::new().join("tags").unwrap();
+
+        let error = sitemap.check_generated(vec![(tags.clone(), template.clone())]).err().unwrap();
+        assert_eq!(collisions(error), vec![vec![dir.join("tags/index.md"), template.clone()]]);
+        assert!(sitemap.check_generated(vec![(tags.join("web").unwrap(), template)]).is_ok());
+    }
+
+    #[test]
+    fn flattened_page_collisions() {
+        let dir = src_dir("flattened", &["index.md", "foo.md", "a/foo.md", "b/bar.md", "c/bar.md"]);
+        let error = SiteMap::new(&dir).err().unwrap();
+        assert_eq!(collisions(error), vec![
+            vec![dir.join("b/bar.md"), dir.join("c/bar.md")],
+            vec![dir.join("a/foo.md"), dir.join("foo.md")],
+        ]);
+    }
 }
@@ -0,0 +1,32 @@
+//! Turning text into URI segments and HTML ids
+//!
+//! Taxonomy terms and heading ids are both made from free text with
+//! `slugify`, so `Web Dev` is written `web-dev` in either.
+
+/// Turns `text` into a URI segment, by lowercasing it and replacing every
+/// run of characters other than letters and digits with a hyphen
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::with_capacity(text.len());
+    for ch in text.chars() {
+        if ch.is_alphanumeric() {
+            slug.extend(ch.to_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn slugify_text() {
+        assert_eq!(slugify("Rust"), "rust");
+        assert_eq!(slugify("  Web Dev & Design! "), "web-dev-design");
+        assert_eq!(slugify("C++"), "c");
+        assert_eq!(slugify("日本語"), "日本語");
+        assert_eq!(slugify("!!"), "");
+    }
+}
@@ -0,0 +1,235 @@
+//! Taxonomies, e.g tags and categories
+//!
+//! Each taxonomy named in the `taxonomies` configuration field is collected
+//! from the front matter field of the same name across every page, e.g
+//!
+//! ```yaml
+//! tags: [rust, web]
+//! ```
+//!
+//! A taxonomy gets a page listing its terms at `/<taxonomy>`, rendered with
+//! the `<taxonomy>/list.html` template, and each term gets a page listing its
+//! pages at `/<taxonomy>/<term>`, rendered with `<taxonomy>/single.html`.
+
+use crate::uri::Uri;
+use crate::page::{Page, PageMap};
+use crate::slug::slugify;
+use crate::error::{self, Error, Result};
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use serde::Serialize;
+use serde_yaml::Value;
+
+/// A taxonomy and every term used by pages in it
+#[derive(Debug, Serialize)]
+pub struct Taxonomy<'a> {
+    pub name: String,
+    pub uri: Uri,
+    /// The terms of this taxonomy, ordered by slug
+    pub terms: Vec<Term<'a>>,
+}
+
+/// A term of a taxonomy, e.g a single tag
+#[derive(Debug, Serialize)]
+pub struct Term<'a> {
+    /// The term as first written in front matter
+    pub name: String,
+    /// The term as it appears in its URI
+    pub slug: String,
+    pub uri: Uri,
+    /// The pages with this term, newest first
+    pub pages: Vec<&'a Page>,
+}
+
+impl<'a> Taxonomy<'a> {
+    /// Collects the taxonomy `name` from the front matter of every page
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the taxonomy, and of its front matter field
+    /// * `pagemap` - The pages to collect terms from
+    pub fn new(name: &str, pagemap: &'a PageMap) -> Result<Self> {
+        let uri = slug_uri(&Uri::new(), name)
+            .expect("taxonomy names are checked by Config::load");
+
+        // Pages are visited in order, so that which spelling of a term is
+        // kept does not change between builds
+        let mut pages: Vec<&Page> = pagemap.0.values().collect();
+        pages.sort_by(|a, b| a.uri.cmp(&b.uri));
+
+        let results: Vec<Result<Vec<(String, &Page)>>> = pages.into_iter()
+            .map(|page| terms(page, name)
+                .map(|terms| terms.into_iter().map(|term| (term, page)).collect()))
+            .collect();
+
+        let mut terms: BTreeMap<String, Term> = BTreeMap::new();
+        for (term, page) in error::collect(results)?.into_iter().flatten() {
+            let slug = slugify(&term);
+            let term = terms.entry(slug.clone())
+                .or_insert_with(|| Term {
+                    uri: slug_uri(&uri, &slug).expect("slugs are valid uri segments"),
+                    name: term,
+                    slug,
+                    pages: Vec::new(),
+                });
+            if !term.pages.iter().any(|other| other.uri == page.uri) {
+                term.pages.push(page);
+            }
+        }
+
+        let mut terms: Vec<Term> = terms.into_values().collect();
+        for term in &mut terms {
+            term.pages.sort_by_cached_key(|page| (Reverse(page.date), page.uri.clone()));
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            uri,
+            terms,
+        })
+    }
+
+    /// The name of the template this taxonomy's list of terms is rendered with
+    pub fn list_template(&self) -> String {
+        format!("{}/list.html", self.name)
+    }
+
+    /// The name of the template each of this taxonomy's terms is rendered with
+    pub fn single_template(&self) -> String {
+        format!("{}/single.html", self.name)
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(transparent)]
+pub struct TaxonomyMap<'a>(pub BTreeMap<String, Taxonomy<'a>>);
+impl<'a> TaxonomyMap<'a> {
+    /// Collects every taxonomy in `names` from the pages of `pagemap`
+    ///
+    /// # Arguments
+    ///
+    /// * `names` - The names of the taxonomies
+    /// * `pagemap` - The pages to collect terms from
+    pub fn new(names: &[String], pagemap: &'a PageMap) -> Result<Self> {
+        let results: Vec<Result<Taxonomy>> = names.iter()
+            .map(|name| Taxonomy::new(name, pagemap))
+            .collect();
+
+        let map = error::collect(results)?
+            .into_iter()
+            .map(|taxonomy| (taxonomy.name.clone(), taxonomy))
+            .collect();
+        Ok(Self(map))
+    }
+
+    /// Returns the URI of every page rendered for the taxonomies, along with
+    /// the template it is rendered with, e.g to check them against the URIs
+    /// of source files
+    ///
+    /// # Arguments
+    ///
+    /// * `template_dir` - The path of the template directory
+    pub fn uris(&self, template_dir: &Path) -> Vec<(Uri, PathBuf)> {
+        self.0.values()
+            .flat_map(|taxonomy| {
+                let list = (taxonomy.uri.clone(), template_dir.join(taxonomy.list_template()));
+                let single = template_dir.join(taxonomy.single_template());
+                std::iter::once(list)
+                    .chain(taxonomy.terms.iter().map(move |term| (term.uri.clone(), single.clone())))
+            })
+            .collect()
+    }
+}
+
+/// Returns the terms `page` has in the taxonomy `name`
+///
+/// A front matter field may hold either a single term or a list of them.
+fn terms(page: &Page, name: &str) -> Result<Vec<String>> {
+    let invalid = || Error::FrontMatter {
+        path: page.src.clone(),
+        line: None,
+        column: None,
+        message: format!("\"{}\" must be a string or a list of strings", name),
+    };
+    let empty = |term: &str| Error::FrontMatter {
+        path: page.src.clone(),
+        line: None,
+        column: None,
+        message: format!("\"{}\" term \"{}\" has no letters or digits", name, term),
+    };
+
+    let terms = match page.extra.get(name) {
+        None | Some(Value::Null) => Vec::new(),
+        Some(Value::String(term)) => vec![term.clone()],
+        Some(Value::Sequence(terms)) => terms.iter()
+            .map(|term| term.as_str().map(String::from).ok_or_else(invalid))
+            .collect::<Result<_>>()?,
+        Some(_) => return Err(invalid()),
+    };
+
+    match terms.iter().find(|term| slugify(term).is_empty()) {
+        Some(term) => Err(empty(term)),
+        None => Ok(terms),
+    }
+}
+
+/// Returns `uri` joined with `slug`, if `slug` is a valid uri segment
+fn slug_uri(uri: &Uri, slug: &str) -> Option<Uri> {
+    if slug.is_empty() {
+        return None;
+    }
+    uri.join(slug)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::date;
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    fn page(name: &str, date: &str, tags: &str) -> Page {
+        Page {
+            src: PathBuf::from(format!("{}.md", name)),
+            date: date::parse(date),
+            extra: HashMap::from([
+                ("tags".to_string(), serde_yaml::from_str(tags).unwrap()),
+            ]),
+            ..Page::test(Uri::new().join(name).unwrap())
+        }
+    }
+
+    fn site(pages: Vec<Page>) -> PageMap {
+        PageMap(pages.into_iter().map(|page| (page.uri.clone(), page)).collect())
+    }
+
+    #[test]
+    fn collect_terms() {
+        let pagemap = site(vec![
+            page("old", "2023-01-01", "[Rust, web]"),
+            page("new", "2023-02-01", "[rust]"),
+            page("other", "2023-03-01", "Web"),
+        ]);
+        let tags = Taxonomy::new("tags", &pagemap).unwrap();
+        assert_eq!(tags.uri.to_string(), "/tags");
+
+        let slugs: Vec<&str> = tags.terms.iter().map(|term| term.slug.as_str()).collect();
+        assert_eq!(slugs, ["rust", "web"]);
+
+        let rust = &tags.terms[0];
+        assert_eq!(rust.uri.to_string(), "/tags/rust");
+        let pages: Vec<String> = rust.pages.iter().map(|page| page.uri.to_string()).collect();
+        assert_eq!(pages, ["/new", "/old"]);
+    }
+
+    #[test]
+    fn invalid_terms_are_errors() {
+        let pagemap = site(vec![page("a", "2023-01-01", "{ not: a list }")]);
+        assert!(matches!(Taxonomy::new("tags", &pagemap), Err(Error::FrontMatter { .. })));
+
+        let pagemap = site(vec![page("a", "2023-01-01", "[\"!!\"]")]);
+        assert!(matches!(Taxonomy::new("tags", &pagemap), Err(Error::FrontMatter { .. })));
+    }
+}
@@ -0,0 +1,99 @@
+//! Tables of contents
+//!
+//! Every heading of a page is given an `id`, and the headings are gathered
+//! into a tree exposed to templates as `page.toc`, e.g
+//!
+//! ```text
+//! {% for h in page.toc %}
+//!     <a href="#{{ h.id }}">{{ h.text }}</a>
+//! {% endfor %}
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+/// A heading, along with the headings nested under it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TocEntry {
+    /// The heading's level, from 1 for `<h1>` to 6 for `<h6>`
+    pub level: u8,
+    /// The heading's text, without any markup
+    pub text: String,
+    /// The `id` of the heading element
+    pub id: String,
+    /// The headings nested under this one
+    pub children: Vec<TocEntry>,
+}
+
+/// Nests a page's headings, given in document order, into a tree
+///
+/// A heading is nested under the closest heading before it with a lower
+/// level. Headings with no such heading are at the top of the tree, even
+/// if they skip levels.
+///
+/// # Arguments
+///
+/// * `headings` - The level, text and id of every heading
+pub fn build(headings: Vec<(u8, String, String)>) -> Vec<TocEntry> {
+    let mut toc = Vec::new();
+    // The chain of open entries, from the top of the tree down
+    let mut stack: Vec<TocEntry> = Vec::new();
+
+    for (level, text, id) in headings {
+        while stack.last().is_some_and(|open| open.level >= level) {
+            close(&mut stack, &mut toc);
+        }
+        stack.push(TocEntry { level, text, id, children: Vec::new() });
+    }
+    while !stack.is_empty() {
+        close(&mut stack, &mut toc);
+    }
+    toc
+}
+
+/// Pops the innermost open entry, adding it to its parent or to `toc`
+fn close(stack: &mut Vec<TocEntry>, toc: &mut Vec<TocEntry>) {
+    let entry = stack.pop().expect("only called with open entries");
+    match stack.last_mut() {
+        Some(parent) => parent.children.push(entry),
+        None => toc.push(entry),
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    fn heading(level: u8, id: &str) -> (u8, String, String) {
+        (level, id.to_uppercase(), id.to_string())
+    }
+
+    fn ids(toc: &[TocEntry]) -> Vec<String> {
+        toc.iter()
+            .map(|entry| if entry.children.is_empty() {
+                entry.id.clone()
+            } else {
+                format!("{}({})", entry.id, ids(&entry.children).join(" "))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn nest_headings() {
+        let toc = build(vec![
+            heading(1, "a"),
+            heading(2, "b"),
+            heading(3, "c"),
+            heading(2, "d"),
+            heading(1, "e"),
+            heading(3, "f"),
+        ]);
+        assert_eq!(ids(&toc), ["a(b(c) d)", "e(f)"]);
+        assert_eq!(toc[0].text, "A");
+    }
+
+    #[test]
+    fn skipped_levels_at_the_top() {
+        let toc = build(vec![heading(3, "a"), heading(2, "b"), heading(3, "c")]);
+        assert_eq!(ids(&toc), ["a", "b(c)"]);
+    }
+}
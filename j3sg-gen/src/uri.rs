@@ -7,15 +7,17 @@
 //! In addition, a URI also uniquely determines an output file path,
 //! given a base output directory, given by.
 //!
-//! ```
+//! ```text
 //! out_dir + uri + "index.html"
 //! ```
 //!
 //! where `+` denotes joining paths together
+//!
+//! A Uri's `Display` form, e.g `/foo/bar`, is its canonical string form,
+//! and is what templates see. For printing to a terminal, use `Uri::colored`.
 
 use colored::*;
 use std::path::{Path, PathBuf, Component};
-use std::hash::{Hash, Hasher};
 use std::fmt;
 use serde::{Serialize, Serializer};
 
@@ -30,7 +32,7 @@ use serde::{Serialize, Serializer};
 ///
 /// Internally, a Uri is a non-absolute PathBuf. 
 /// (Because it's easier to make a non-absolute path absolute than the other way around)
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Uri(PathBuf);
 impl Uri {
     /// Helper function which validates if a path would make a valid internal
@@ -39,7 +41,7 @@ impl Uri {
     /// Specifically, it follows the rules:
     /// * It must be non-absolute
     /// * It must not contain current or parent directory components,
-    /// i.e no path segments of the form ".." or "."
+    ///   i.e no path segments of the form ".." or "."
     /// * Path segments must use only the following ASCII characters
     ///     * Alphanumeric characters
     ///     * Hyphens, underscores, or periods
@@ -108,32 +110,48 @@ impl Uri {
         self.0.parent().map(|path| Uri(path.to_owned()))
     }
     
-    /// Returns a vector of ancestors
+    /// Returns the ancestors of this Uri, from the root down, not
+    /// including this Uri itself
+    ///
+    /// E.g the ancestors of `/foo/bar/baz` are `/`, `/foo` and `/foo/bar`,
+    /// and the root has none.
     pub fn ancestors(&self) -> Vec<Self> {
-        todo!();
+        let mut ancestors: Vec<Self> = self.0.ancestors()
+            .skip(1)
+            .map(|path| Uri(path.to_owned()))
+            .collect();
+        ancestors.reverse();
+        ancestors
     }
 
-    /// Returns a new Uri joined with the given path 
+    /// Returns a new Uri joined with the given path, or None if
+    /// the path would not make a valid Uri
     ///
     /// # Arguments
     ///
     /// * `path` - The path to be joined to this Uri
-    pub fn join<P>(&self, path: P) -> Result<Uri, String> 
+    pub fn join<P>(&self, path: P) -> Option<Uri> 
     where
         P: AsRef<Path>
     {
         let path = path.as_ref();
         if Self::valid_path(path) {
-            Ok(Uri(self.0.join(path)))
+            Some(Uri(self.0.join(path)))
         } else {
-            Err(format!("Tried to join invalid path {:?}", path))
+            None
         }
     }
 
     /// Whether or not this Uri is the root uri, `/`
     pub fn is_root(&self) -> bool {
         let path = self.0.as_path();
-        path.parent() == None
+        path.parent().is_none()
+    }
+
+    /// Returns a wrapper which displays this Uri with colored segments,
+    /// for printing to a terminal
+    pub fn colored(&self) -> ColoredUri<'_> {
+        ColoredUri(self)
     }
 
     /// Returns the last segment of this Uri
@@ -153,9 +171,8 @@ impl fmt::Display for Uri {
         if self.is_root() {
             write!(f, "/")?;
         } else {
-            let path = self.0.as_path();
-            for segment in path {
-                write!(f, "/{}", segment.to_str().unwrap().purple())?;
+            for segment in self.0.iter() {
+                write!(f, "/{}", segment.to_str().unwrap())?;
             }
         }
         Ok(())
@@ -163,21 +180,7 @@ impl fmt::Display for Uri {
 }
 impl fmt::Debug for Uri {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.to_string().purple())
-    }
-}
-impl PartialEq for Uri {
-    fn eq(&self, other: &Self) -> bool {
-        self.to_string() == other.to_string()
-    }
-}
-impl Eq for Uri {}
-impl Hash for Uri {
-    fn hash<H>(&self, state: &mut H) 
-    where 
-        H: Hasher
-    {
-        self.to_string().hash(state);
+        write!(f, "Uri({})", self)
     }
 }
 impl Serialize for Uri {
@@ -185,7 +188,22 @@ impl Serialize for Uri {
     where 
         S: Serializer
     {
-        serializer.serialize_str(&self.to_string())
+        serializer.collect_str(self)
+    }
+}
+
+/// A Uri displayed with colored segments, see `Uri::colored`
+pub struct ColoredUri<'a>(&'a Uri);
+impl fmt::Display for ColoredUri<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_root() {
+            write!(f, "/")?;
+        } else {
+            for segment in self.0.0.iter() {
+                write!(f, "/{}", segment.to_str().unwrap().purple())?;
+            }
+        }
+        Ok(())
     }
 }
 
@@ -205,6 +223,46 @@ pub mod test {
         assert_eq!(uri, Uri(PathBuf::new()));
     }
 
+    #[test]
+    fn display_is_canonical() {
+        colored::control::set_override(true);
+        let uri = Uri::new().join("foo/bar").unwrap();
+        assert_eq!(uri.to_string(), "/foo/bar");
+        assert_eq!(Uri::new().to_string(), "/");
+        assert_ne!(uri.colored().to_string(), "/foo/bar");
+    }
+
+    #[test]
+    fn serialize_is_canonical() {
+        colored::control::set_override(true);
+        let uri = Uri::new().join("foo").unwrap().join("bar").unwrap();
+        assert_eq!(serde_json::to_string(&uri).unwrap(), "\"/foo/bar\"");
+    }
+
+    #[test]
+    fn ancestors_from_root_down() {
+        let uri = Uri::new().join("foo/bar/baz").unwrap();
+        let ancestors: Vec<String> = uri.ancestors().iter().map(Uri::to_string).collect();
+        assert_eq!(ancestors, ["/", "/foo", "/foo/bar"]);
+        assert!(Uri::new().ancestors().is_empty());
+    }
+
+    #[test]
+    fn equal_uris_hash_equally() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+
+        let hash = |uri: &Uri| {
+            let mut hasher = DefaultHasher::new();
+            uri.hash(&mut hasher);
+            hasher.finish()
+        };
+        let a = Uri::new().join("foo/bar").unwrap();
+        let b = Uri::new().join("foo").unwrap().join("bar").unwrap();
+        assert_eq!(a, b);
+        assert_eq!(hash(&a), hash(&b));
+    }
+
     #[test]
     fn create_valid_pathbuf() {
         let mut buf = PathBuf::new();
@@ -0,0 +1,73 @@
+//! Regenerating the site whenever its sources change
+//!
+//! The source, template and static directories are watched for changes.
+//! Since saving a single file in an editor often produces several events,
+//! events are debounced: the site is regenerated once no more events have
+//! arrived for `DEBOUNCE`.
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+
+use std::path::Path;
+use std::sync::mpsc;
+use std::time::Duration;
+use log::{error, info};
+use notify::{RecursiveMode, Watcher};
+
+/// How long to wait for further changes before regenerating
+pub const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Generates the site, then regenerates it whenever a file in the source,
+/// template or static directory changes
+///
+/// Generation errors are logged rather than returned, so that the author
+/// can fix them and carry on. After each successful generation,
+/// `on_rebuild` is called.
+///
+/// This function only returns if the directories could not be watched.
+///
+/// # Arguments
+///
+/// * `config` - The site configuration
+/// * `on_rebuild` - Called after every successful generation
+pub fn watch<F>(config: &Config, mut on_rebuild: F) -> Result<()>
+where
+    F: FnMut()
+{
+    let mut rebuild = || match crate::generate(config) {
+        Ok(()) => {
+            info!("Site generated");
+            on_rebuild();
+        }
+        Err(e) => error!("{}", e),
+    };
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)
+        .map_err(|source| watch_error(&config.src_dir, source))?;
+    for dir in [&config.src_dir, &config.template_dir, &config.static_dir] {
+        if dir.is_dir() {
+            watcher.watch(dir, RecursiveMode::Recursive)
+                .map_err(|source| watch_error(dir, source))?;
+            info!("Watching {}", dir.display());
+        }
+    }
+
+    rebuild();
+    while let Ok(event) = rx.recv() {
+        if let Err(e) = event {
+            error!("{}", e);
+            continue;
+        }
+        while rx.recv_timeout(DEBOUNCE).is_ok() {}
+
+        info!("Change detected, regenerating");
+        rebuild();
+    }
+
+    Ok(())
+}
+
+fn watch_error(path: &Path, source: notify::Error) -> Error {
+    Error::Watch { path: path.to_owned(), source }
+}
@@ -1,22 +1,55 @@
+mod reload;
+
 use std::error::Error;
 use log::info;
-use env_logger::Env;
 
 use actix_files as fs;
-use actix_web::{ middleware::Logger, App, HttpServer };
+use actix_web::{ body, dev, http::header, middleware::Logger, web, App, HttpServer };
 use openssl::ssl::{ SslAcceptor, SslFiletype, SslMethod };
+use j3sg_gen::Config;
+
+pub use reload::LiveReload;
+
+/// Starts serving the directories given by `config`
+///
+/// If `live_reload` is given, a script is injected into every served
+/// HTML page which reloads it whenever `LiveReload::reload` is called.
+pub async fn serve(
+    config: &Config,
+    tls_pair: Option<(String, String)>,
+    live_reload: Option<LiveReload>
+) -> Result<(), Box<dyn Error>> {
+    let bind_addr = config.bind.clone();
+    let static_dir = config.static_dir.clone();
+    let static_mount = format!("/{}", config.static_mount.to_string_lossy());
+    let out_dir = config.out_dir.clone();
 
-/// Starts serving from current working directory
-pub async fn serve(bind_addr: String, tls_pair: Option<(String, String)>) -> Result<(), Box<dyn Error>> {
     let http_server = HttpServer::new(move || {
-        App::new()
-            .wrap(Logger::new("%a \"%r\" %s"))
+        let inject = live_reload.is_some();
+        let mut app = App::new()
+            .app_data(web::Data::new(live_reload.clone().unwrap_or_default()))
+            .wrap_fn(move |req, srv| {
+                let res = dev::Service::call(srv, req);
+                async move {
+                    let res = res.await?;
+                    if inject && is_html(&res) {
+                        inject_script(res).await
+                    } else {
+                        Ok(res.map_into_boxed_body())
+                    }
+                }
+            })
+            .wrap(Logger::new("%a \"%r\" %s"));
+        if inject {
+            app = app.route(reload::EVENTS_PATH, web::get().to(reload::events));
+        }
+        app
             .service(
-                fs::Files::new("/static", "static")
+                fs::Files::new(&static_mount, &static_dir)
                     .show_files_listing()
             )
             .service(
-                fs::Files::new("/", "public")
+                fs::Files::new("/", &out_dir)
                     .index_file("index.html")
             )
     });
@@ -46,3 +79,25 @@ pub async fn serve(bind_addr: String, tls_pair: Option<(String, String)>) -> Res
 
     Ok(())
 }
+
+/// Whether `res` is a successful HTML response
+fn is_html<B>(res: &dev::ServiceResponse<B>) -> bool {
+    res.status().is_success() && res.headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/html"))
+}
+
+/// Injects the live reload script into the body of the HTML response `res`
+async fn inject_script(
+    res: dev::ServiceResponse
+) -> Result<dev::ServiceResponse, actix_web::Error> {
+    let (req, res) = res.into_parts();
+    let (mut res, body) = res.into_parts();
+    let html = body::to_bytes(body).await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))?;
+
+    res.headers_mut().remove(header::CONTENT_LENGTH);
+    let res = res.set_body(reload::inject_script(&html));
+    Ok(dev::ServiceResponse::new(req, res).map_into_boxed_body())
+}
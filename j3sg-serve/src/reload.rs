@@ -0,0 +1,87 @@
+//! Live reloading of open browser tabs
+//!
+//! In development, a small script is injected into every served HTML page.
+//! It listens for Server-Sent Events on `EVENTS_PATH`, and reloads the page
+//! whenever the site is rebuilt.
+
+use std::convert::Infallible;
+use actix_web::{ web, HttpResponse };
+use actix_web::web::Bytes;
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::StreamExt;
+
+/// The path browsers listen for reload events on
+pub const EVENTS_PATH: &str = "/__j3sg/livereload";
+
+/// The script injected into served HTML pages
+const CLIENT_SCRIPT: &str = "<script>\
+new EventSource(\"/__j3sg/livereload\").onmessage = () => location.reload();\
+</script>";
+
+/// A handle for telling open browser tabs to reload
+#[derive(Clone)]
+pub struct LiveReload(broadcast::Sender<()>);
+
+impl LiveReload {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(16);
+        Self(tx)
+    }
+
+    /// Tells every connected browser tab to reload
+    pub fn reload(&self) {
+        // Sending only fails if no tabs are connected
+        let _ = self.0.send(());
+    }
+}
+
+impl Default for LiveReload {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Streams a reload event to the browser after every rebuild
+pub async fn events(reload: web::Data<LiveReload>) -> HttpResponse {
+    let stream = BroadcastStream::new(reload.0.subscribe())
+        .map(|_| Ok::<_, Infallible>(Bytes::from_static(b"data: reload\n\n")));
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(stream)
+}
+
+/// Inserts the live reload script before the closing `</body>` tag of
+/// `html`, or at the end if there is none
+pub fn inject_script(html: &[u8]) -> Vec<u8> {
+    let position = html.windows(7)
+        .rposition(|window| window.eq_ignore_ascii_case(b"</body>"))
+        .unwrap_or(html.len());
+
+    let mut injected = Vec::with_capacity(html.len() + CLIENT_SCRIPT.len());
+    injected.extend_from_slice(&html[..position]);
+    injected.extend_from_slice(CLIENT_SCRIPT.as_bytes());
+    injected.extend_from_slice(&html[position..]);
+    injected
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn inject_before_closing_body() {
+        let html = b"<html><body><p>Hi</p></BODY></html>";
+        let expected = format!("<html><body><p>Hi</p>{}</BODY></html>", CLIENT_SCRIPT);
+        assert_eq!(inject_script(html), expected.as_bytes());
+    }
+
+    #[test]
+    fn inject_without_body() {
+        let html = b"<p>Hi</p>";
+        let expected = format!("<p>Hi</p>{}", CLIENT_SCRIPT);
+        assert_eq!(inject_script(html), expected.as_bytes());
+    }
+}
@@ -1,49 +1,92 @@
 use env_logger::Env;
+use log::error;
 use std::error::Error;
-use std::path::Path;
+use j3sg_gen::{Config, CONFIG_FILE};
+use j3sg_serve::LiveReload;
 
 #[actix_web::main]
-async fn main() -> Result<(), Box<dyn Error>> {
+async fn main() {
+    env_logger::init_from_env(Env::default().default_filter_or("info"));
+
+    if let Err(e) = run().await {
+        error!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn run() -> Result<(), Box<dyn Error>> {
     let args: Vec<String> = std::env::args().collect();
 
-    env_logger::init_from_env(Env::default().default_filter_or("info"));
-    
-    let src_dir = Path::new("src");
-    let static_dir = Path::new("static");
-    let out_dir = Path::new("public");
-    let template_dir = Path::new("templates");
+    let mut config = Config::load(CONFIG_FILE)?;
+
+    let flag = |short: &str, long: &str| args.iter()
+        .skip(2)
+        .any(|arg| arg == short || arg == long);
 
     let verb = match args.get(1) {
         Some(s) => { match &s[..] {
             "gen" | "generate" | "G" => Verb::Generate,
             "init" | "initialize" | "I" => Verb::Init,
             "srv" | "serve" | "S" => Verb::Serve {
-                bind: "127.0.0.1:5000".to_string(),
-                tls: None
+                tls: None,
+                watch: flag("-w", "--watch"),
             },
+            "watch" | "W" => Verb::Watch,
             _ => Verb::Help 
         } },
         None => Verb::Help,
     };
 
+    config.drafts |= flag("-d", "--drafts");
+    // Previews include drafts, so they are kept out of the output directory
+    if matches!(verb, Verb::Serve { watch: true, .. }) {
+        config = config.preview();
+    }
+
     match verb {
         Verb::Generate => {
-            j3sg_gen::generate(src_dir, out_dir, template_dir)?;
+            j3sg_gen::generate(&config)?;
+        }
+        Verb::Serve { tls, watch } => {
+            let live_reload = if watch {
+                let live_reload = LiveReload::new();
+                let config = config.clone();
+                let on_rebuild = live_reload.clone();
+                std::thread::spawn(move || {
+                    if let Err(e) = j3sg_gen::watch(&config, || on_rebuild.reload()) {
+                        error!("{}", e);
+                    }
+                });
+                Some(live_reload)
+            } else {
+                None
+            };
+            j3sg_serve::serve(&config, tls, live_reload).await?;
         }
-        Verb::Serve { bind, tls } => {
-            j3sg_serve::serve(bind, tls).await?;
+        Verb::Watch => {
+            j3sg_gen::watch(&config, || {})?;
         }
         Verb::Init => {
-            j3sg_gen::init(src_dir, out_dir, template_dir, static_dir)?;
+            j3sg_gen::init(&config, CONFIG_FILE)?;
         }
         Verb::Help => {
             println!("USAGE: j3sg COMMAND");
-            println!("");
+            println!();
             println!("COMMANDS:");
-            println!("    gen | generate | G");
+            println!("    gen | generate | G [-d | --drafts]");
             println!("        Compiles the static site into ./public");
-            println!("    srv | serve | S");
+            println!("        With --drafts, also builds drafts and pages dated in the future");
+            println!("    srv | serve | S [-w | --watch]");
             println!("        Serves files from ./public and ./static");
+            println!("        With --watch, instead builds a preview including drafts into");
+            println!("        ./.j3sg-cache/preview, regenerating it whenever a source file changes");
+            println!("        and reloading open pages in the browser");
+            println!("    watch | W [-d | --drafts]");
+            println!("        Regenerates the site whenever a source file changes");
+            println!("    init | initialize | I");
+            println!("        Creates a new site in the current directory");
+            println!();
+            println!("Directories and the server address can be changed in ./{}", CONFIG_FILE);
         }
     }
 
@@ -53,9 +96,10 @@ async fn main() -> Result<(), Box<dyn Error>> {
 enum Verb {
     Generate,
     Serve {
-        bind: String,
         tls: Option<(String, String)>,
+        watch: bool,
     },
+    Watch,
     Init,
     Help,
 }